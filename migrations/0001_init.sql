@@ -0,0 +1,248 @@
+-- Schema of the former init.sql
+--
+-- Written to also adopt a database created by any earlier init.sql: existing tables are kept
+-- and brought up to date.
+
+CREATE TABLE IF NOT EXISTS company (
+  id SERIAL PRIMARY KEY,
+  srtn_cd CHAR(6),
+  isin_cd CHAR(12),
+  mrkt_ctg VARCHAR(6),
+  itms_nm VARCHAR(240),
+  crno VARCHAR(20),
+  corp_nm VARCHAR(240),
+  valid_from DATE NOT NULL DEFAULT CURRENT_DATE,
+  valid_to DATE -- NULL while current
+);
+-- Created by an earlier init.sql
+ALTER TABLE company DROP CONSTRAINT IF EXISTS company_srtn_cd_key;
+ALTER TABLE company
+  ADD COLUMN IF NOT EXISTS valid_from DATE NOT NULL DEFAULT CURRENT_DATE,
+  ADD COLUMN IF NOT EXISTS valid_to DATE;
+DROP TABLE IF EXISTS company_previous;
+CREATE UNIQUE INDEX IF NOT EXISTS company_current ON company(srtn_cd) WHERE valid_to IS NULL;
+CREATE INDEX IF NOT EXISTS company_history ON company(srtn_cd, valid_from);
+
+CREATE TABLE IF NOT EXISTS dart_code (
+  id SERIAL PRIMARY KEY,
+  code CHAR(8),
+  stock_code CHAR(6),
+  name TEXT,
+  date DATE
+);
+
+CREATE TABLE IF NOT EXISTS price (
+  id SERIAL PRIMARY KEY,
+  bas_dt DATE,
+  srtn_cd CHAR(6),
+  isin_cd CHAR(12),
+  itms_nm VARCHAR(120),
+  mrkt_ctg VARCHAR(6),
+  clpr INTEGER,
+  vs INTEGER,
+  flt_rt REAL,
+  mkp INTEGER,
+  hipr INTEGER,
+  lopr INTEGER,
+  trqu DECIMAL,
+  tr_prc DECIMAL,
+  lstg_st_cnt DECIMAL,
+  mrkt_tot_amt DECIMAL,
+  UNIQUE(bas_dt, srtn_cd)
+);
+
+CREATE TABLE IF NOT EXISTS price_weekly (
+  id SERIAL PRIMARY KEY,
+  srtn_cd CHAR(6),
+  year INTEGER,
+  week INTEGER,
+  opening_date DATE,
+  closing_date DATE,
+  open DECIMAL,
+  close DECIMAL,
+  high DECIMAL,
+  low DECIMAL,
+  volume DECIMAL,
+  trading_value DECIMAL,
+  base_stock_cnt DECIMAL,
+  UNIQUE(srtn_cd, year, week)
+);
+
+CREATE TABLE IF NOT EXISTS price_monthly (
+  id SERIAL PRIMARY KEY,
+  srtn_cd CHAR(6),
+  year INTEGER,
+  month INTEGER,
+  opening_date DATE,
+  closing_date DATE,
+  open DECIMAL,
+  close DECIMAL,
+  high DECIMAL,
+  low DECIMAL,
+  volume DECIMAL,
+  trading_value DECIMAL,
+  base_stock_cnt DECIMAL,
+  UNIQUE(srtn_cd, year, month)
+);
+
+CREATE TABLE IF NOT EXISTS price_quarterly (
+  id SERIAL PRIMARY KEY,
+  srtn_cd CHAR(6),
+  year INTEGER,
+  quarter INTEGER,
+  opening_date DATE,
+  closing_date DATE,
+  open DECIMAL,
+  close DECIMAL,
+  high DECIMAL,
+  low DECIMAL,
+  volume DECIMAL,
+  trading_value DECIMAL,
+  base_stock_cnt DECIMAL,
+  UNIQUE(srtn_cd, year, quarter)
+);
+
+CREATE TABLE IF NOT EXISTS price_yearly (
+  id SERIAL PRIMARY KEY,
+  srtn_cd CHAR(6),
+  year INTEGER,
+  opening_date DATE,
+  closing_date DATE,
+  open DECIMAL,
+  close DECIMAL,
+  high DECIMAL,
+  low DECIMAL,
+  volume DECIMAL,
+  trading_value DECIMAL,
+  base_stock_cnt DECIMAL,
+  UNIQUE(srtn_cd, year)
+);
+
+CREATE TABLE IF NOT EXISTS price_backfill (
+  srtn_cd CHAR(6) PRIMARY KEY,
+  start_date DATE,
+  next_date DATE,
+  updated TIMESTAMPTZ DEFAULT NOW()
+);
+
+
+-------------------- US Stock --------------------
+CREATE TABLE IF NOT EXISTS ticker (
+  id SERIAL PRIMARY KEY,
+  cik_str CHAR(10),
+  ticker VARCHAR(10),
+  title TEXT,
+  valid_from DATE NOT NULL DEFAULT CURRENT_DATE,
+  valid_to DATE -- NULL while current
+);
+-- Created by an earlier init.sql
+ALTER TABLE ticker
+  ADD COLUMN IF NOT EXISTS valid_from DATE NOT NULL DEFAULT CURRENT_DATE,
+  ADD COLUMN IF NOT EXISTS valid_to DATE;
+DROP TABLE IF EXISTS ticker_previous;
+CREATE UNIQUE INDEX IF NOT EXISTS ticker_current ON ticker(ticker) WHERE valid_to IS NULL;
+CREATE INDEX IF NOT EXISTS ticker_history ON ticker(ticker, valid_from);
+
+CREATE TABLE IF NOT EXISTS price_us (
+  id SERIAL PRIMARY KEY,
+  ticker VARCHAR(10),
+  date DATE,
+  open DECIMAL,
+  high DECIMAL,
+  low DECIMAL,
+  close DECIMAL,
+  adj_close DECIMAL,
+  volume DECIMAL,
+  UNIQUE(ticker, date)
+);
+
+CREATE TABLE IF NOT EXISTS price_us_weekly (
+  id SERIAL PRIMARY KEY,
+  ticker VARCHAR(10),
+  year INTEGER,
+  week INTEGER,
+  opening_date DATE,
+  closing_date DATE,
+  open DECIMAL,
+  high DECIMAL,
+  low DECIMAL,
+  close DECIMAL,
+  volume DECIMAL,
+  UNIQUE(ticker, year, week)
+);
+
+CREATE TABLE IF NOT EXISTS price_us_monthly (
+  id SERIAL PRIMARY KEY,
+  ticker VARCHAR(10),
+  year INTEGER,
+  month INTEGER,
+  opening_date DATE,
+  closing_date DATE,
+  open DECIMAL,
+  high DECIMAL,
+  low DECIMAL,
+  close DECIMAL,
+  volume DECIMAL,
+  UNIQUE(ticker, year, month)
+);
+
+CREATE TABLE IF NOT EXISTS price_us_quarterly (
+  id SERIAL PRIMARY KEY,
+  ticker VARCHAR(10),
+  year INTEGER,
+  quarter INTEGER,
+  opening_date DATE,
+  closing_date DATE,
+  open DECIMAL,
+  high DECIMAL,
+  low DECIMAL,
+  close DECIMAL,
+  volume DECIMAL,
+  UNIQUE(ticker, year, quarter)
+);
+
+CREATE TABLE IF NOT EXISTS price_us_yearly (
+  id SERIAL PRIMARY KEY,
+  ticker VARCHAR(10),
+  year INTEGER,
+  opening_date DATE,
+  closing_date DATE,
+  open DECIMAL,
+  high DECIMAL,
+  low DECIMAL,
+  close DECIMAL,
+  volume DECIMAL,
+  UNIQUE(ticker, year)
+);
+
+CREATE TABLE IF NOT EXISTS price_us_backfill (
+  ticker VARCHAR(10) PRIMARY KEY,
+  start_date DATE,
+  next_date DATE,
+  updated TIMESTAMPTZ DEFAULT NOW()
+);
+
+-------------------- Jobs --------------------
+CREATE TABLE IF NOT EXISTS scheduled_job (
+  name VARCHAR(40) PRIMARY KEY,
+  last_run TIMESTAMPTZ,
+  last_success TIMESTAMPTZ,
+  last_error TEXT,
+  last_error_at TIMESTAMPTZ
+);
+
+CREATE TABLE IF NOT EXISTS job (
+  id UUID PRIMARY KEY,
+  kind VARCHAR(40),
+  target VARCHAR(40),
+  state VARCHAR(10),
+  done INTEGER DEFAULT 0,
+  total INTEGER DEFAULT 0,
+  error TEXT,
+  result TEXT,
+  created_at TIMESTAMPTZ DEFAULT NOW(),
+  started_at TIMESTAMPTZ,
+  finished_at TIMESTAMPTZ
+);
+-- Created by an earlier init.sql
+ALTER TABLE job ADD COLUMN IF NOT EXISTS result TEXT;
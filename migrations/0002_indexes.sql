@@ -0,0 +1,14 @@
+-- Built concurrently so that writes go on during a deploy; this runs outside a transaction, one
+-- statement at a time, and an index left invalid by a failed build is dropped and built again
+
+-- Prices are read per symbol, while the unique key of price starts with the date
+DROP INDEX CONCURRENTLY IF EXISTS price_srtn_cd_bas_dt;
+CREATE INDEX CONCURRENTLY price_srtn_cd_bas_dt ON price(srtn_cd, bas_dt);
+
+-- DART codes are looked up by stock code
+DROP INDEX CONCURRENTLY IF EXISTS dart_code_stock_code;
+CREATE INDEX CONCURRENTLY dart_code_stock_code ON dart_code(stock_code);
+
+-- Workers claim the oldest queued job
+DROP INDEX CONCURRENTLY IF EXISTS job_state_created_at;
+CREATE INDEX CONCURRENTLY job_state_created_at ON job(state, created_at);
@@ -0,0 +1,7 @@
+-- Workers stamp the jobs they run so that only abandoned ones are queued again
+ALTER TABLE job
+  ADD COLUMN IF NOT EXISTS owner UUID,
+  ADD COLUMN IF NOT EXISTS heartbeat_at TIMESTAMPTZ;
+
+-- Finished jobs are removed after `jobs.retention_days`
+CREATE INDEX IF NOT EXISTS job_finished_at ON job(finished_at);
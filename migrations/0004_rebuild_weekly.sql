@@ -0,0 +1,5 @@
+-- Weekly rows written before `prices.week_start` existed are keyed by the old numbering,
+-- in which days before the first Sunday of a year were week 0; rebuild them from daily prices
+INSERT INTO job(id, kind, state)
+SELECT gen_random_uuid(), 'rebuild_weekly', 'queued'
+WHERE EXISTS (SELECT 1 FROM price_weekly) OR EXISTS (SELECT 1 FROM price_us_weekly);
@@ -0,0 +1,5 @@
+-- The ticker table of an earlier init.sql had no unique key; keep the first current row of a
+-- ticker listed twice, so that listings can be merged
+DELETE FROM ticker a USING ticker b
+  WHERE a.ticker = b.ticker AND a.valid_to IS NULL AND b.valid_to IS NULL AND a.id > b.id;
+CREATE UNIQUE INDEX IF NOT EXISTS ticker_current ON ticker(ticker) WHERE valid_to IS NULL;
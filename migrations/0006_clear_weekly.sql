@@ -0,0 +1,12 @@
+-- Migration 4 only queued the rebuild; until it finished, weekly upserts wrote keys of the new
+-- numbering next to old rows that stand for other weeks. Unless a rebuild has succeeded since,
+-- drop the weekly rows and let a queued rebuild write them again from daily prices
+DELETE FROM price_weekly
+WHERE NOT EXISTS (SELECT 1 FROM job WHERE kind = 'rebuild_weekly' AND state = 'succeeded');
+DELETE FROM price_us_weekly
+WHERE NOT EXISTS (SELECT 1 FROM job WHERE kind = 'rebuild_weekly' AND state = 'succeeded');
+
+INSERT INTO job(id, kind, state)
+SELECT gen_random_uuid(), 'rebuild_weekly', 'queued'
+WHERE NOT EXISTS (SELECT 1 FROM job WHERE kind = 'rebuild_weekly' AND state IN ('succeeded', 'queued'))
+  AND (EXISTS (SELECT 1 FROM price) OR EXISTS (SELECT 1 FROM price_us));
@@ -1,27 +1,65 @@
-pub fn build() -> actix_web::Scope {
+use crate::utils::{auth, rate_limit};
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    middleware::from_fn,
+};
+
+pub fn build() -> actix_web::Scope<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    // The last middleware wrapped runs first
     actix_web::web::scope("/v1")
+        .wrap(from_fn(auth::authorize))
+        .wrap(from_fn(rate_limit::limit))
+        .service(crate::services::prices_us::handler_post_rebuild)
         .service(crate::services::prices_us::handler_post)
         .service(crate::services::prices_us::handler_put)
         .service(crate::services::prices_us::handler_get_latest)
         .service(crate::services::prices_us::handler_get_daily)
+        .service(crate::services::prices_us::handler_get_bars)
         .service(crate::services::prices_us::handler_get_weekly)
+        .service(crate::services::prices_us::handler_get_period)
         .service(crate::services::prices_us::handler_get_exists)
+        .service(crate::services::prices_us::handler_del_one)
         .service(crate::services::prices_us::handler_del)
+        .service(crate::services::prices::handler_post_rebuild)
+        .service(crate::services::prices::handler_post_market)
         .service(crate::services::prices::handler_post)
         .service(crate::services::prices::handler_put)
         .service(crate::services::prices::handler_get_snapshot)
         .service(crate::services::prices::handler_get_latest)
         .service(crate::services::prices::handler_get_daily)
+        .service(crate::services::prices::handler_get_bars)
         .service(crate::services::prices::handler_get_weekly)
+        .service(crate::services::prices::handler_get_period)
         .service(crate::services::prices::handler_get_exists)
+        .service(crate::services::prices::handler_del_one)
         .service(crate::services::prices::handler_del)
+        .service(crate::services::companies::handler_post_rollback)
         .service(crate::services::companies::handler_post)
+        .service(crate::services::companies::handler_get_listings)
+        .service(crate::services::companies::handler_get_history)
         .service(crate::services::companies::handler_get)
         .service(crate::services::dart::handler_get_code)
         .service(crate::services::dart::handler_post_code)
+        .service(crate::services::dart::handler_post_code_rollback)
         .service(crate::services::dart::handler_get_index)
         .service(crate::services::dart::handler_get_statement)
+        .service(crate::services::tickers::handler_get_listings)
+        .service(crate::services::tickers::handler_get_history)
         .service(crate::services::tickers::handler_get)
         .service(crate::services::tickers::handler_post)
+        .service(crate::services::tickers::handler_post_rollback)
         .service(crate::services::edgar::handler_get)
+        .service(crate::services::calendar::handler_get)
+        .service(crate::services::jobs::handler_get)
+        .service(crate::services::jobs::handler_get_one)
+        .service(crate::services::jobs::handler_post_rebuild_weekly)
 }
@@ -3,6 +3,7 @@ mod model;
 mod services;
 mod utils;
 
+use tracing::{Level, event};
 use tracing_subscriber::prelude::*;
 
 #[actix_web::main]
@@ -27,10 +28,30 @@ async fn main() -> std::io::Result<()> {
     let app_settings = utils::settings::Settings::instance();
     let server_addr = format!("{}:{}", app_settings.server.host, app_settings.server.port);
 
+    // Bring the DB schema up to date; `--migrate` only does this
+    let migrate_only = std::env::args().any(|arg| arg == "--migrate");
+    if migrate_only || app_settings.migrations.on_startup {
+        let versions = utils::migrate::run()
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        event!(Level::INFO, ?versions, "schema is up to date");
+    }
+    if migrate_only {
+        return Ok(());
+    }
+
+    // Run background ingestion jobs
+    services::jobs::spawn_workers();
+    services::jobs::spawn_scheduler();
+
     actix_web::HttpServer::new(move || {
         actix_web::App::new()
             .wrap(utils::cors::build())
             .wrap(tracing_actix_web::TracingLogger::default())
+            .wrap(actix_web::middleware::from_fn(utils::metrics::track))
+            .service(services::health::handler_get_health)
+            .service(services::health::handler_get_ready)
+            .service(services::metrics::handler_get)
             .service(api::v1::build())
     })
     .bind(server_addr)?
@@ -1,6 +1,9 @@
+mod calendar;
 pub mod dart;
 pub mod edgar;
 mod error;
+mod health;
+mod job;
 pub mod krx;
 mod res_body;
 mod stock_company;
@@ -10,7 +13,10 @@ mod ticker;
 pub mod web;
 pub mod xbrl;
 
+pub use calendar::*;
 pub use error::*;
+pub use health::*;
+pub use job::*;
 pub use res_body::*;
 pub use stock_company::StockCompany;
 pub use stock_price::StockPrice;
@@ -0,0 +1,31 @@
+use crate::utils::datetime::{date_deserialize, date_serialize};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct TradingDayRes {
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub date: time::Date,
+    pub is_trading_day: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub open: Option<time::OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub close: Option<time::OffsetDateTime>,
+    pub half_day: bool,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct CalendarRes {
+    pub market: String,
+    pub timezone: String,
+    pub is_open: bool,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub next_open: Option<time::OffsetDateTime>,
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub last_completed_trading_day: time::Date,
+    pub days: Vec<TradingDayRes>,
+}
@@ -1,5 +1,4 @@
 // https://opendart.fss.or.kr/guide/detail.do?apiGrpCd=DS003&apiId=2022001
-use deadpool_redis::redis;
 
 #[allow(unused)]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -18,33 +17,6 @@ pub struct IndexRes {
     pub list: Option<Vec<IndexItem>>,
 }
 
-impl redis::ToSingleRedisArg for IndexRes {}
-
-impl redis::ToRedisArgs for IndexRes {
-    fn write_redis_args<W>(&self, out: &mut W)
-    where
-        W: ?Sized + redis::RedisWrite,
-    {
-        out.write_arg(serde_json::to_string(self).unwrap().as_bytes())
-    }
-}
-
-impl redis::FromRedisValue for IndexRes {
-    fn from_redis_value(v: redis::Value) -> Result<Self, redis::ParsingError> {
-        match v {
-            redis::Value::BulkString(ref bytes) => {
-                let msg = std::str::from_utf8(bytes).unwrap();
-                let object = serde_json::from_str::<Self>(msg).unwrap();
-                Ok(object)
-            }
-            _ => Err(redis::ParsingError::from(format!(
-                "{:?} (response was {:?})",
-                "Response type not dart::IndexRes compatible", v
-            ))),
-        }
-    }
-}
-
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct IndexItem {
     pub bsns_year: String,
@@ -67,33 +39,6 @@ pub struct StatementRes {
     pub list: Option<Vec<StatementItem>>,
 }
 
-impl redis::ToSingleRedisArg for StatementRes {}
-
-impl redis::ToRedisArgs for StatementRes {
-    fn write_redis_args<W>(&self, out: &mut W)
-    where
-        W: ?Sized + redis::RedisWrite,
-    {
-        out.write_arg(serde_json::to_string(self).unwrap().as_bytes())
-    }
-}
-
-impl redis::FromRedisValue for StatementRes {
-    fn from_redis_value(v: redis::Value) -> Result<Self, redis::ParsingError> {
-        match v {
-            redis::Value::BulkString(ref bytes) => {
-                let msg = std::str::from_utf8(bytes).unwrap();
-                let object = serde_json::from_str::<Self>(msg).unwrap();
-                Ok(object)
-            }
-            _ => Err(redis::ParsingError::from(format!(
-                "{:?} (response was {:?})",
-                "Response type not dart::StatementRes compatible", v
-            ))),
-        }
-    }
-}
-
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct StatementItem {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -1,6 +1,5 @@
 use super::xbrl;
 use crate::utils::datetime::{date_opt_deserialize, date_opt_serialize};
-use deadpool_redis::redis;
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -49,33 +48,6 @@ pub struct StatementRes {
     pub financing_cash_flow: Vec<StatementItem>,
 }
 
-impl redis::ToSingleRedisArg for StatementRes {}
-
-impl redis::ToRedisArgs for StatementRes {
-    fn write_redis_args<W>(&self, out: &mut W)
-    where
-        W: ?Sized + redis::RedisWrite,
-    {
-        out.write_arg(serde_json::to_string(self).unwrap().as_bytes())
-    }
-}
-
-impl redis::FromRedisValue for StatementRes {
-    fn from_redis_value(v: redis::Value) -> Result<Self, redis::ParsingError> {
-        match v {
-            redis::Value::BulkString(ref bytes) => {
-                let msg = std::str::from_utf8(bytes)?;
-                let object = serde_json::from_str::<Self>(msg).unwrap();
-                Ok(object)
-            }
-            _ => Err(redis::ParsingError::from(format!(
-                "{:?} (response was {:?})",
-                "Response type not edgar::StatementRes compatible", v
-            ))),
-        }
-    }
-}
-
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct StatementItem {
     #[serde(
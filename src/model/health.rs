@@ -0,0 +1,15 @@
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct DependencyRes {
+    pub ok: bool,
+    pub latency_ms: u128,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ReadinessRes {
+    pub ready: bool,
+    pub degraded: bool, // ready, but without Redis; responses are served uncached
+    pub db: DependencyRes,
+    pub cache: DependencyRes,
+    pub settings: DependencyRes,
+}
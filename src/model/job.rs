@@ -0,0 +1,53 @@
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ScheduledJobRes {
+    pub name: String,
+    pub enabled: bool,
+    pub cron: String,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub next_run: Option<time::OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_run: Option<time::OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_success: Option<time::OffsetDateTime>,
+    pub last_error: Option<String>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub last_error_at: Option<time::OffsetDateTime>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct JobRes {
+    pub id: uuid::Uuid,
+    pub kind: String,
+    pub target: Option<String>,
+    pub state: String,
+    pub done: i32,
+    pub total: i32,
+    pub error: Option<String>,
+    pub result: Option<serde_json::Value>, // report of jobs that produce one
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: time::OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub started_at: Option<time::OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub finished_at: Option<time::OffsetDateTime>,
+}
+
+impl From<&tokio_postgres::Row> for JobRes {
+    fn from(value: &tokio_postgres::Row) -> Self {
+        Self {
+            id: value.get("id"),
+            kind: value.get("kind"),
+            target: value.get("target"),
+            state: value.get("state"),
+            done: value.get("done"),
+            total: value.get("total"),
+            error: value.get("error"),
+            result: value
+                .get::<_, Option<String>>("result")
+                .and_then(|v| serde_json::from_str(&v).ok()),
+            created_at: value.get("created_at"),
+            started_at: value.get("started_at"),
+            finished_at: value.get("finished_at"),
+        }
+    }
+}
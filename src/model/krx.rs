@@ -1,5 +1,3 @@
-use deadpool_redis::redis;
-
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ResBody {
     #[serde(
@@ -20,33 +18,6 @@ impl From<super::web::krx::ResBody> for ResBody {
     }
 }
 
-impl redis::ToSingleRedisArg for ResBody {}
-
-impl redis::ToRedisArgs for ResBody {
-    fn write_redis_args<W>(&self, out: &mut W)
-    where
-        W: ?Sized + redis::RedisWrite,
-    {
-        out.write_arg(serde_json::to_string(self).unwrap().as_bytes())
-    }
-}
-
-impl redis::FromRedisValue for ResBody {
-    fn from_redis_value(v: redis::Value) -> Result<Self, redis::ParsingError> {
-        match v {
-            redis::Value::BulkString(ref bytes) => {
-                let msg = std::str::from_utf8(bytes)?;
-                let object = serde_json::from_str::<Self>(msg).unwrap();
-                Ok(object)
-            }
-            _ => Err(redis::ParsingError::from(format!(
-                "{:?} (response was {:?})",
-                "Response type not krx::ResBody compatible", v
-            ))),
-        }
-    }
-}
-
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Price {
     pub sect_tp_nm: String,
@@ -110,30 +81,3 @@ impl From<super::web::krx::Price> for Price {
         }
     }
 }
-
-impl redis::ToSingleRedisArg for Price {}
-
-impl redis::ToRedisArgs for Price {
-    fn write_redis_args<W>(&self, out: &mut W)
-    where
-        W: ?Sized + redis::RedisWrite,
-    {
-        out.write_arg(serde_json::to_string(self).unwrap().as_bytes())
-    }
-}
-
-impl redis::FromRedisValue for Price {
-    fn from_redis_value(v: redis::Value) -> Result<Self, redis::ParsingError> {
-        match v {
-            redis::Value::BulkString(ref bytes) => {
-                let msg = std::str::from_utf8(bytes)?;
-                let object = serde_json::from_str::<Self>(msg).unwrap();
-                Ok(object)
-            }
-            _ => Err(redis::ParsingError::from(format!(
-                "{:?} (response was {:?})",
-                "Response type not krx::Price compatible", v
-            ))),
-        }
-    }
-}
@@ -1,4 +1,6 @@
-use crate::utils::datetime::date_serialize;
+use crate::utils::datetime::{
+    date_deserialize, date_opt_deserialize, date_opt_serialize, date_serialize,
+};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -28,6 +30,14 @@ impl From<&tokio_postgres::Row> for StockCompanySearchRes {
 pub struct StockWeeklyPriceRes {
     pub srtn_cd: String,
     pub prices: Vec<StockWeekPrice>,
+    #[serde(default)]
+    pub stale: bool, // behind the last completed trading day; a refresh has been requested
+    #[serde(
+        default,
+        serialize_with = "date_opt_serialize",
+        deserialize_with = "date_opt_deserialize"
+    )]
+    pub next_cursor: Option<time::Date>, // pass as `cursor` for the next page; null on the last page
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -65,10 +75,100 @@ impl From<&tokio_postgres::Row> for StockWeekPrice {
     }
 }
 
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct StockPeriodPriceRes {
+    pub srtn_cd: String,
+    pub period: String,
+    pub prices: Vec<StockPeriodPrice>,
+    #[serde(default)]
+    pub stale: bool,
+    #[serde(
+        default,
+        serialize_with = "date_opt_serialize",
+        deserialize_with = "date_opt_deserialize"
+    )]
+    pub next_cursor: Option<time::Date>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct StockPeriodPrice {
+    pub year: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub month: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quarter: Option<i32>,
+    #[serde(serialize_with = "date_serialize")]
+    pub opening_date: time::Date,
+    #[serde(serialize_with = "date_serialize")]
+    pub closing_date: time::Date,
+    pub open: rust_decimal::Decimal,
+    pub close: rust_decimal::Decimal,
+    pub high: rust_decimal::Decimal,
+    pub low: rust_decimal::Decimal,
+    pub volume: rust_decimal::Decimal,
+    pub trading_value: rust_decimal::Decimal,
+    pub base_stock_cnt: rust_decimal::Decimal,
+}
+
+impl From<&tokio_postgres::Row> for StockPeriodPrice {
+    fn from(value: &tokio_postgres::Row) -> Self {
+        Self {
+            year: value.get("year"),
+            month: value.try_get("month").ok(),
+            quarter: value.try_get("quarter").ok(),
+            opening_date: value.get("opening_date"),
+            closing_date: value.get("closing_date"),
+            open: value.get("open"),
+            close: value.get("close"),
+            high: value.get("high"),
+            low: value.get("low"),
+            volume: value.get("volume"),
+            trading_value: value.get("trading_value"),
+            base_stock_cnt: value.get("base_stock_cnt"),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct StockBarsRes {
+    pub srtn_cd: String,
+    pub interval: String,
+    #[serde(serialize_with = "date_serialize")]
+    pub anchor: time::Date,
+    pub bars: Vec<StockBar>,
+    #[serde(default)]
+    pub stale: bool,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct StockBar {
+    #[serde(serialize_with = "date_serialize")]
+    pub start: time::Date,
+    #[serde(serialize_with = "date_serialize")]
+    pub opening_date: time::Date,
+    #[serde(serialize_with = "date_serialize")]
+    pub closing_date: time::Date,
+    pub open: rust_decimal::Decimal,
+    pub close: rust_decimal::Decimal,
+    pub high: rust_decimal::Decimal,
+    pub low: rust_decimal::Decimal,
+    pub volume: rust_decimal::Decimal,
+    pub trading_value: rust_decimal::Decimal,
+    pub base_stock_cnt: rust_decimal::Decimal,
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct StockDayPriceRes {
     pub srtn_cd: String,
     pub prices: Vec<StockDayPrice>,
+    #[serde(default)]
+    pub stale: bool,
+    #[serde(
+        default,
+        serialize_with = "date_opt_serialize",
+        deserialize_with = "date_opt_deserialize"
+    )]
+    pub next_cursor: Option<time::Date>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -109,6 +209,14 @@ pub struct StockPriceExistsRes {
 pub struct StockUSWeeklyPriceRes {
     pub ticker: String,
     pub prices: Vec<StockUSWeekPrice>,
+    #[serde(default)]
+    pub stale: bool,
+    #[serde(
+        default,
+        serialize_with = "date_opt_serialize",
+        deserialize_with = "date_opt_deserialize"
+    )]
+    pub next_cursor: Option<time::Date>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -142,10 +250,94 @@ impl From<&tokio_postgres::Row> for StockUSWeekPrice {
     }
 }
 
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct StockUSPeriodPriceRes {
+    pub ticker: String,
+    pub period: String,
+    pub prices: Vec<StockUSPeriodPrice>,
+    #[serde(default)]
+    pub stale: bool,
+    #[serde(
+        default,
+        serialize_with = "date_opt_serialize",
+        deserialize_with = "date_opt_deserialize"
+    )]
+    pub next_cursor: Option<time::Date>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct StockUSPeriodPrice {
+    pub year: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub month: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quarter: Option<i32>,
+    #[serde(serialize_with = "date_serialize")]
+    pub opening_date: time::Date,
+    #[serde(serialize_with = "date_serialize")]
+    pub closing_date: time::Date,
+    pub open: rust_decimal::Decimal,
+    pub high: rust_decimal::Decimal,
+    pub low: rust_decimal::Decimal,
+    pub close: rust_decimal::Decimal,
+    pub volume: rust_decimal::Decimal,
+}
+
+impl From<&tokio_postgres::Row> for StockUSPeriodPrice {
+    fn from(value: &tokio_postgres::Row) -> Self {
+        Self {
+            year: value.get("year"),
+            month: value.try_get("month").ok(),
+            quarter: value.try_get("quarter").ok(),
+            opening_date: value.get("opening_date"),
+            closing_date: value.get("closing_date"),
+            open: value.get("open"),
+            high: value.get("high"),
+            low: value.get("low"),
+            close: value.get("close"),
+            volume: value.get("volume"),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct StockUSBarsRes {
+    pub ticker: String,
+    pub interval: String,
+    #[serde(serialize_with = "date_serialize")]
+    pub anchor: time::Date,
+    pub bars: Vec<StockUSBar>,
+    #[serde(default)]
+    pub stale: bool,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct StockUSBar {
+    #[serde(serialize_with = "date_serialize")]
+    pub start: time::Date,
+    #[serde(serialize_with = "date_serialize")]
+    pub opening_date: time::Date,
+    #[serde(serialize_with = "date_serialize")]
+    pub closing_date: time::Date,
+    pub open: rust_decimal::Decimal,
+    pub high: rust_decimal::Decimal,
+    pub low: rust_decimal::Decimal,
+    pub close: rust_decimal::Decimal,
+    pub volume: rust_decimal::Decimal,
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct StockUSDayPriceRes {
     pub ticker: String,
     pub prices: Vec<super::StockPriceUS>,
+    #[serde(default)]
+    pub stale: bool,
+    #[serde(
+        default,
+        serialize_with = "date_opt_serialize",
+        deserialize_with = "date_opt_deserialize"
+    )]
+    pub next_cursor: Option<time::Date>,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
@@ -153,3 +345,156 @@ pub struct StockUSPriceExistsRes {
     pub ticker: String,
     pub exists: bool,
 }
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct RebuildRes {
+    pub count: usize, // symbols rebuilt
+    pub changed: u64, // rows inserted or updated
+    pub deleted: u64,
+    pub symbols: Vec<RebuildSymbolRes>, // only the ones with changes
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct RebuildSymbolRes {
+    pub symbol: String,
+    pub changed: u64,
+    pub deleted: u64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct PriceDeleteRes {
+    pub symbol: String,
+    pub deleted: u64, // daily rows
+    pub derived_changed: u64,
+    pub derived_deleted: u64,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct MarketPriceRes {
+    pub days: usize, // trading days published so far
+    #[serde(
+        default,
+        serialize_with = "date_opt_serialize",
+        deserialize_with = "date_opt_deserialize"
+    )]
+    pub latest: Option<time::Date>,
+    pub inserted: u64,  // daily rows new to DB
+    pub symbols: usize, // symbols whose derived prices were updated
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct BackfillRes {
+    pub symbol: String,
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub from: time::Date, // where this run started; later than the start date when resumed
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub to: time::Date,
+    pub chunks: usize,
+    pub fetched: usize, // daily rows downloaded
+    pub inserted: u64,  // daily rows new to DB
+}
+
+/// One version of a listed company; `valid_to` is absent while current
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompanyVersionRes {
+    pub srtn_cd: String,
+    pub isin_cd: String,
+    pub mrkt_ctg: String,
+    pub itms_nm: String,
+    pub crno: String,
+    pub corp_nm: String,
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub valid_from: time::Date,
+    #[serde(
+        default,
+        serialize_with = "date_opt_serialize",
+        deserialize_with = "date_opt_deserialize"
+    )]
+    pub valid_to: Option<time::Date>,
+}
+
+impl From<&tokio_postgres::Row> for CompanyVersionRes {
+    fn from(value: &tokio_postgres::Row) -> Self {
+        Self {
+            srtn_cd: value.get("srtn_cd"),
+            isin_cd: value.get("isin_cd"),
+            mrkt_ctg: value.get("mrkt_ctg"),
+            itms_nm: value.get("itms_nm"),
+            crno: value.get("crno"),
+            corp_nm: value.get("corp_nm"),
+            valid_from: value.get("valid_from"),
+            valid_to: value.get("valid_to"),
+        }
+    }
+}
+
+/// One version of a US ticker; `valid_to` is absent while current
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct TickerVersionRes {
+    pub cik_str: String,
+    pub ticker: String,
+    pub title: String,
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub valid_from: time::Date,
+    #[serde(
+        default,
+        serialize_with = "date_opt_serialize",
+        deserialize_with = "date_opt_deserialize"
+    )]
+    pub valid_to: Option<time::Date>,
+}
+
+impl From<&tokio_postgres::Row> for TickerVersionRes {
+    fn from(value: &tokio_postgres::Row) -> Self {
+        Self {
+            cik_str: value.get("cik_str"),
+            ticker: value.get("ticker"),
+            title: value.get("title"),
+            valid_from: value.get("valid_from"),
+            valid_to: value.get("valid_to"),
+        }
+    }
+}
+
+/// Symbols that appeared or disappeared between two dates, inclusive
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ListingsRes<T> {
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub from: time::Date,
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub to: time::Date,
+    pub listed: Vec<T>,   // first version after a gap, from the date it appeared
+    pub delisted: Vec<T>, // last version before a gap, until the date it disappeared
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ListingRebuildRes {
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub as_of: time::Date,
+    pub rows: u64,
+    pub listed: u64,
+    pub changed: u64, // renamed, moved market and so on
+    pub delisted: u64,
+}
@@ -26,9 +26,9 @@ pub struct StockPriceRHeader {
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct StockPriceBody {
-    pub num_of_rows: u16,
-    pub page_no: u16,
-    pub total_count: u16,
+    pub num_of_rows: u32,
+    pub page_no: u32,
+    pub total_count: u32,
     pub items: StockPriceItems,
 }
 
@@ -71,3 +71,25 @@ pub struct StockPriceItem {
     #[serde(with = "rust_decimal::serde::str")]
     pub mrkt_tot_amt: rust_decimal::Decimal,
 }
+
+impl From<&tokio_postgres::Row> for StockPriceItem {
+    fn from(value: &tokio_postgres::Row) -> Self {
+        Self {
+            bas_dt: value.get("bas_dt"),
+            srtn_cd: value.get("srtn_cd"),
+            isin_cd: value.get("isin_cd"),
+            itms_nm: value.get("itms_nm"),
+            mrkt_ctg: value.get("mrkt_ctg"),
+            clpr: value.get("clpr"),
+            vs: value.get("vs"),
+            flt_rt: value.get("flt_rt"),
+            mkp: value.get("mkp"),
+            hipr: value.get("hipr"),
+            lopr: value.get("lopr"),
+            trqu: value.get("trqu"),
+            tr_prc: value.get("tr_prc"),
+            lstg_st_cnt: value.get("lstg_st_cnt"),
+            mrkt_tot_amt: value.get("mrkt_tot_amt"),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use rust_decimal::prelude::FromPrimitive;
 
-use crate::utils::datetime::{date_deserialize, date_serialize};
 use crate::utils::Result;
+use crate::utils::datetime::{date_deserialize, date_serialize};
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct StockPriceUS {
@@ -12,6 +12,7 @@ pub struct Chart {
 
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct Result {
+    #[serde(default)] // missing when there is no session in the range
     pub timestamp: Vec<i64>,
     pub indicators: Indicators,
     #[serde(flatten)]
@@ -25,7 +26,8 @@ pub struct Indicators {
     pub adj_close: Vec<AdjClose>,
 }
 
-#[derive(Debug, Clone, serde::Deserialize)]
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(default)]
 pub struct Quote {
     pub open: Vec<f32>,
     pub close: Vec<f32>,
@@ -36,6 +38,6 @@ pub struct Quote {
 
 #[derive(Debug, Clone, serde::Deserialize)]
 pub struct AdjClose {
-    #[serde(alias = "adjclose")]
+    #[serde(default, alias = "adjclose")]
     pub adj_close: Vec<f32>,
 }
@@ -1,6 +1,10 @@
+pub mod calendar;
 pub mod companies;
 pub mod dart;
 pub mod edgar;
+pub mod health;
+pub mod jobs;
+pub mod metrics;
 pub mod prices;
 pub mod prices_us;
 pub mod tickers;
@@ -0,0 +1,34 @@
+use super::provider;
+use crate::utils::{Result, calendar::Market, datetime::date_opt_deserialize, error::Error};
+
+#[derive(Debug, serde::Deserialize)]
+struct ParamsRange {
+    #[serde(default, deserialize_with = "date_opt_deserialize")]
+    from: Option<time::Date>,
+    #[serde(default, deserialize_with = "date_opt_deserialize")]
+    to: Option<time::Date>,
+}
+
+/// Trading days and session times; defaults to the next 30 days
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::get("/calendar/{market}")]
+pub async fn handler_get(
+    req: actix_web::HttpRequest,
+    market: actix_web::web::Path<String>,
+    range: actix_web::web::Query<ParamsRange>,
+) -> Result<actix_web::HttpResponse> {
+    let market = market.parse::<Market>()?;
+    let today = market.local(time::OffsetDateTime::now_utc()).date();
+    let from = range.from.unwrap_or(today);
+    let to = match range.to {
+        Some(to) => to,
+        None => from
+            .checked_add(time::Duration::days(30))
+            .ok_or_else(|| Error::E400BadRequest("to is out of range".into()))?,
+    };
+
+    let res = provider::get_calendar(market, from, to)?;
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().json(res))
+}
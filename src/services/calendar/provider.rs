@@ -0,0 +1,41 @@
+use crate::model::{CalendarRes, TradingDayRes};
+use crate::utils::{Result, calendar::Market, error::Error};
+
+#[tracing::instrument(err)]
+pub fn get_calendar(market: Market, from: time::Date, to: time::Date) -> Result<CalendarRes> {
+    if from > to || (to - from).whole_days() > 366 {
+        return Err(Error::E400BadRequest(
+            "from must not be after to, and the range must be a year at most".into(),
+        ));
+    }
+
+    let mut days = Vec::new();
+    for date in (0..=(to - from).whole_days()).map(|n| from + time::Duration::days(n)) {
+        if !market.is_known(date) {
+            return Err(Error::E400BadRequest(format!(
+                "holidays of {} are not known for {}; add them to calendar.{}_holidays",
+                market.as_str(),
+                date.year(),
+                market.as_str()
+            )));
+        }
+        let session = market.session(date);
+        days.push(TradingDayRes {
+            date,
+            is_trading_day: session.is_some(),
+            open: session.map(|s| s.open),
+            close: session.map(|s| s.close),
+            half_day: session.is_some_and(|s| s.half_day),
+        });
+    }
+
+    let now = time::OffsetDateTime::now_utc();
+    Ok(CalendarRes {
+        market: market.as_str().to_string(),
+        timezone: market.timezone().to_string(),
+        is_open: market.is_open(now),
+        next_open: market.next_open(now),
+        last_completed_trading_day: market.last_completed_trading_day(now),
+        days,
+    })
+}
@@ -1,18 +1,27 @@
-use crate::model::{StockCompany, StockCompanySearchRes};
-use crate::utils::{Result, db, error::Error, settings::Settings};
+use crate::model::{
+    CompanyVersionRes, ListingRebuildRes, ListingsRes, StockCompany, StockCompanySearchRes,
+};
+use crate::utils::{
+    Result,
+    calendar::Market,
+    db,
+    error::Error,
+    metrics::{self, Upstream},
+    settings::Settings,
+};
+
+// Trading days to step back while looking for published data
+const MAX_RETRIES: u32 = 10;
 
 #[tracing::instrument(err)]
-pub async fn build_company_db() -> Result<()> {
+pub async fn build_company_db() -> Result<ListingRebuildRes> {
     let web_client = reqwest::Client::new();
     let key = Settings::instance().keys.data_go_kr.clone();
     let url = Settings::instance().urls.kr_company.clone();
     let req_url = reqwest::Url::parse(&url).unwrap();
     let host = req_url.host_str().unwrap();
     let format = time::macros::format_description!("[year][month][day]");
-    let mut base_date = time::OffsetDateTime::now_utc()
-        .date()
-        .previous_day()
-        .unwrap();
+    let mut base_date = Market::Krx.last_completed_trading_day(time::OffsetDateTime::now_utc());
 
     let mut req_url_with_params = reqwest::Url::parse_with_params(
         &url,
@@ -26,19 +35,25 @@ pub async fn build_company_db() -> Result<()> {
     .unwrap();
 
     // Get all codes
-    let mut res = web_client
-        .get(req_url_with_params.clone())
-        .header(reqwest::header::HOST, host)
-        .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8")
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<StockCompany>()
-        .await?;
+    let mut res = metrics::send(
+        Upstream::DataGoKr,
+        web_client
+            .get(req_url_with_params.clone())
+            .header(reqwest::header::HOST, host)
+            .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8"),
+    )
+    .await?
+    .json::<StockCompany>()
+    .await?;
 
-    // If total_count in body is 0, retry with previous date until data comes in
+    // If total_count in body is 0, the day is not published yet; retry with previous trading days
+    let mut retries = 0;
     while res.response.body.total_count < 1 {
-        base_date = base_date.previous_day().unwrap();
+        retries += 1;
+        if retries > MAX_RETRIES {
+            return Err(Error::E404NotFound("No data found from web".into()));
+        }
+        base_date = Market::Krx.previous_trading_day(base_date);
 
         req_url_with_params = reqwest::Url::parse_with_params(
             &url,
@@ -51,52 +66,63 @@ pub async fn build_company_db() -> Result<()> {
         )
         .unwrap();
 
-        res = web_client
-            .get(req_url_with_params)
-            .header(reqwest::header::HOST, host)
-            .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8")
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<StockCompany>()
-            .await?;
+        res = metrics::send(
+            Upstream::DataGoKr,
+            web_client
+                .get(req_url_with_params)
+                .header(reqwest::header::HOST, host)
+                .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8"),
+        )
+        .await?
+        .json::<StockCompany>()
+        .await?;
     }
 
-    // Store in DB
-    const SQL_CLEAR: &str = "TRUNCATE TABLE company RESTART IDENTITY";
-    const SQL_INSERT: &str = "
-    INSERT INTO company(srtn_cd, isin_cd, mrkt_ctg, itms_nm, crno, corp_nm)
-    VALUES ($1::CHAR(6), $2::CHAR(12), $3::VARCHAR(6), $4::VARCHAR(240), $5::VARCHAR(20), $6::VARCHAR(240));";
-
-    let mut db_client = db::pool().get().await?;
-    let transaction = db_client.transaction().await?;
-    let sql_insert = transaction.prepare(SQL_INSERT).await?;
-
-    transaction.simple_query(SQL_CLEAR).await?;
-
-    for item in &res.response.body.items.item {
-        transaction
-            .query(
-                &sql_insert,
-                &[
-                    &&item.srtn_cd[1..],
-                    &item.isin_cd,
-                    &item.mrkt_ctg,
-                    &item.itms_nm,
-                    &item.crno,
-                    &item.corp_nm,
-                ],
-            )
-            .await?;
-    }
+    // Store in DB; companies that changed or disappeared keep their earlier versions
+    const VALUES: [&str; 5] = ["isin_cd", "mrkt_ctg", "itms_nm", "crno", "corp_nm"];
+
+    let items = &res.response.body.items.item;
+    let codes: Vec<&str> = items.iter().map(|item| &item.srtn_cd[1..]).collect();
+    let rows: Vec<_> = items
+        .iter()
+        .zip(&codes)
+        .map(|(item, code)| -> db::CopyRow {
+            vec![
+                code,
+                &item.isin_cd,
+                &item.mrkt_ctg,
+                &item.itms_nm,
+                &item.crno,
+                &item.corp_nm,
+            ]
+        })
+        .collect();
 
-    Ok(transaction.commit().await?)
+    let check = db::Check {
+        min_rows: Settings::instance().reload.company_min_rows,
+        required: &["srtn_cd", "isin_cd", "itms_nm"],
+    };
+    let merged = db::merge_history("company", "srtn_cd", &VALUES, &rows, &check, base_date).await?;
+
+    Ok(ListingRebuildRes {
+        as_of: base_date,
+        rows: merged.rows,
+        listed: merged.listed,
+        changed: merged.changed,
+        delisted: merged.delisted,
+    })
+}
+
+/// Put back the companies replaced by the last build
+#[tracing::instrument(err)]
+pub async fn rollback_company_db() -> Result<()> {
+    db::swap_back("company").await
 }
 
 #[tracing::instrument]
 pub async fn get_company(search_word: &str) -> Result<Vec<StockCompanySearchRes>> {
-    const SQL: &str =
-        "SELECT * FROM company WHERE itms_nm ILIKE $1 OR corp_nm ILIKE $1 OR srtn_cd ILIKE $1;";
+    const SQL: &str = "SELECT * FROM company WHERE valid_to IS NULL
+            AND (itms_nm ILIKE $1 OR corp_nm ILIKE $1 OR srtn_cd ILIKE $1);";
 
     let word = format!("%{}%", search_word);
 
@@ -112,3 +138,51 @@ pub async fn get_company(search_word: &str) -> Result<Vec<StockCompanySearchRes>
 
     Ok(res)
 }
+
+/// Every version of a company, oldest first
+#[tracing::instrument]
+pub async fn get_company_history(code: &str) -> Result<Vec<CompanyVersionRes>> {
+    const SQL: &str = "SELECT * FROM company WHERE srtn_cd=$1::CHAR(6) ORDER BY valid_from;";
+
+    let rows = db::query(SQL, &[&code]).await?;
+    if rows.is_empty() {
+        return Err(Error::E404NotFound("stock company".into()));
+    }
+
+    let res = rows
+        .into_iter()
+        .map(|row| CompanyVersionRes::from(&row))
+        .collect();
+
+    Ok(res)
+}
+
+/// Companies listed or delisted between two dates; a version following another directly is a change, not a listing
+#[tracing::instrument]
+pub async fn get_company_listings(
+    from: time::Date,
+    to: time::Date,
+) -> Result<ListingsRes<CompanyVersionRes>> {
+    const SQL_LISTED: &str = "
+        SELECT * FROM company c WHERE c.valid_from BETWEEN $1::DATE AND $2::DATE
+            AND NOT EXISTS (
+                SELECT 1 FROM company p WHERE p.srtn_cd=c.srtn_cd AND p.valid_to=c.valid_from
+            )
+        ORDER BY c.valid_from, c.srtn_cd;";
+    const SQL_DELISTED: &str = "
+        SELECT * FROM company c WHERE c.valid_to BETWEEN $1::DATE AND $2::DATE
+            AND NOT EXISTS (
+                SELECT 1 FROM company n WHERE n.srtn_cd=c.srtn_cd AND n.valid_from=c.valid_to
+            )
+        ORDER BY c.valid_to, c.srtn_cd;";
+
+    let listed = db::query(SQL_LISTED, &[&from, &to]).await?;
+    let delisted = db::query(SQL_DELISTED, &[&from, &to]).await?;
+
+    Ok(ListingsRes {
+        from,
+        to,
+        listed: listed.iter().map(CompanyVersionRes::from).collect(),
+        delisted: delisted.iter().map(CompanyVersionRes::from).collect(),
+    })
+}
@@ -1,8 +1,7 @@
 use super::provider;
-use crate::model::dart;
-use crate::utils::{cache, error::Error, Result};
+use crate::utils::{Result, cache, error::Error};
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::get("/dart/code/{stock_code}")]
 pub async fn handler_get_code(
     req: actix_web::HttpRequest,
@@ -14,7 +13,7 @@ pub async fn handler_get_code(
     Ok(actix_web::HttpResponse::Ok().body(res))
 }
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::post("/dart/code")]
 pub async fn handler_post_code(req: actix_web::HttpRequest) -> Result<actix_web::HttpResponse> {
     provider::build_code_db().await?;
@@ -23,6 +22,18 @@ pub async fn handler_post_code(req: actix_web::HttpRequest) -> Result<actix_web:
     Ok(actix_web::HttpResponse::Created().finish())
 }
 
+/// Put back the DART codes replaced by the last build
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::post("/dart/code/rollback")]
+pub async fn handler_post_code_rollback(
+    req: actix_web::HttpRequest,
+) -> Result<actix_web::HttpResponse> {
+    provider::rollback_code_db().await?;
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().finish())
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct ParamsIndex {
     corp_code: String,
@@ -30,7 +41,7 @@ struct ParamsIndex {
     idx_code: String,
 }
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::get("/dart/index/{corp_code}/{report_code}/{idx_code}")]
 pub async fn handler_get_index(
     req: actix_web::HttpRequest,
@@ -57,15 +68,12 @@ pub async fn handler_get_index(
         return Err(Error::E400BadRequest("invalid idx_code".into()));
     }
 
-    // cache first
-    if let Some(data_in_cache) = cache::get::<Option<dart::IndexRes>>(req.path()).await? {
-        return Ok(actix_web::HttpResponse::Ok().json(data_in_cache));
-    };
-
-    let res = provider::get_index(&params.corp_code, &params.report_code, &params.idx_code).await?;
-
-    // Store in cache
-    cache::set(req.path(), &res).await?;
+    // cache first; concurrent requests for the same path share one fetch
+    let params = params.into_inner();
+    let res = cache::get_or_fetch_json(req.path(), cache::policy("dart"), move || async move {
+        provider::get_index(&params.corp_code, &params.report_code, &params.idx_code).await
+    })
+    .await?;
 
     // Return result
     Ok(actix_web::HttpResponse::Ok().json(res))
@@ -78,7 +86,7 @@ struct ParamsStatement {
     fs_div: String,
 }
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::get("/dart/statement/{corp_code}/{report_code}/{fs_div}")]
 pub async fn handler_get_statement(
     req: actix_web::HttpRequest,
@@ -101,16 +109,12 @@ pub async fn handler_get_statement(
         return Err(Error::E400BadRequest("invalid fs_div".into()));
     }
 
-    // cache first
-    if let Some(data_in_cache) = cache::get::<Option<dart::StatementRes>>(req.path()).await? {
-        return Ok(actix_web::HttpResponse::Ok().json(data_in_cache));
-    };
-
-    let res =
-        provider::get_statement(&params.corp_code, &params.report_code, &params.fs_div).await?;
-
-    // Store in cache
-    cache::set(req.path(), &res).await?;
+    // cache first; concurrent requests for the same path share one fetch
+    let params = params.into_inner();
+    let res = cache::get_or_fetch_json(req.path(), cache::policy("dart"), move || async move {
+        provider::get_statement(&params.corp_code, &params.report_code, &params.fs_div).await
+    })
+    .await?;
 
     // Return result
     Ok(actix_web::HttpResponse::Ok().json(res))
@@ -1,5 +1,10 @@
 use crate::model::dart;
-use crate::utils::{Result, db, error::Error, settings::Settings};
+use crate::utils::{
+    Result, db,
+    error::Error,
+    metrics::{self, Upstream},
+    settings::Settings,
+};
 use std::io::Read;
 
 #[tracing::instrument(err)]
@@ -13,16 +18,17 @@ pub async fn build_code_db() -> Result<()> {
         reqwest::Url::parse_with_params(&url, &[("crtfc_key", key.as_str())]).unwrap();
     let host = req_url_with_params.host_str().unwrap();
 
-    let buf: Vec<u8> = web_client
-        .get(req_url_with_params.clone())
-        .header(reqwest::header::HOST, host)
-        .header(reqwest::header::ACCEPT, "application/xml;charset=UTF-8")
-        .send()
-        .await?
-        .error_for_status()?
-        .bytes()
-        .await?
-        .into();
+    let buf: Vec<u8> = metrics::send(
+        Upstream::OpenDart,
+        web_client
+            .get(req_url_with_params.clone())
+            .header(reqwest::header::HOST, host)
+            .header(reqwest::header::ACCEPT, "application/xml;charset=UTF-8"),
+    )
+    .await?
+    .bytes()
+    .await?
+    .into();
 
     // Unzip the downloaded file
     let cursor = std::io::Cursor::new(buf);
@@ -71,34 +77,36 @@ pub async fn build_code_db() -> Result<()> {
         }
     }
 
-    // Store data in DB
-    const SQL_CLEAR: &str = "TRUNCATE TABLE dart_code RESTART IDENTITY";
-    const SQL_INSERT: &str = "INSERT INTO dart_code(code,stock_code,name,date) VALUES ($1::CHAR(8), $2::CHAR(6), $3::TEXT, $4::DATE);";
-
-    let mut db_client = db::pool().get().await?;
-    let transaction = db_client.transaction().await?;
-    let sql_insert = transaction.prepare(SQL_INSERT).await?;
-
-    transaction.simple_query(SQL_CLEAR).await?; // Reset the table
-
-    for item in &codes {
-        transaction
-            .query(
-                &sql_insert,
-                &[
-                    &item.corp_code,
-                    &item.stock_code,
-                    &item.corp_name,
-                    &item.modify_date,
-                ],
-            )
-            .await?;
-    }
+    // Store in DB; readers keep the current table until the new one has been checked and swapped in
+    const COLUMNS: [&str; 4] = ["code", "stock_code", "name", "date"];
+
+    let rows: Vec<_> = codes
+        .iter()
+        .map(|item| -> db::CopyRow {
+            vec![
+                &item.corp_code,
+                &item.stock_code,
+                &item.corp_name,
+                &item.modify_date,
+            ]
+        })
+        .collect();
+
+    let check = db::Check {
+        min_rows: Settings::instance().reload.dart_code_min_rows,
+        required: &["code", "stock_code", "name"],
+    };
+    db::swap_in("dart_code", &COLUMNS, &rows, &check).await?;
 
-    transaction.commit().await?;
     Ok(())
 }
 
+/// Put back the dart codes replaced by the last build
+#[tracing::instrument(err)]
+pub async fn rollback_code_db() -> Result<()> {
+    db::swap_back("dart_code").await
+}
+
 #[tracing::instrument(err)]
 pub async fn get_dart_code(stock_code: &str) -> Result<String> {
     const SQL: &str = "SELECT * FROM dart_code WHERE stock_code=$1::CHAR(6) ORDER BY date DESC;";
@@ -138,15 +146,16 @@ pub async fn get_index(
     )
     .unwrap();
 
-    let mut res = web_client
-        .get(req_url_with_params.clone())
-        .header(reqwest::header::HOST, host)
-        .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8")
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<dart::IndexRes>()
-        .await?;
+    let mut res = metrics::send(
+        Upstream::OpenDart,
+        web_client
+            .get(req_url_with_params.clone())
+            .header(reqwest::header::HOST, host)
+            .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8"),
+    )
+    .await?
+    .json::<dart::IndexRes>()
+    .await?;
 
     // status 013 means data NOT_FOUND
     if res.status == "013" {
@@ -163,15 +172,16 @@ pub async fn get_index(
         )
         .unwrap();
 
-        res = web_client
-            .get(req_url_with_params)
-            .header(reqwest::header::HOST, host)
-            .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8")
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<dart::IndexRes>()
-            .await?;
+        res = metrics::send(
+            Upstream::OpenDart,
+            web_client
+                .get(req_url_with_params)
+                .header(reqwest::header::HOST, host)
+                .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8"),
+        )
+        .await?
+        .json::<dart::IndexRes>()
+        .await?;
     }
 
     Ok(res)
@@ -202,15 +212,16 @@ pub async fn get_statement(
     )
     .unwrap();
 
-    let mut res = web_client
-        .get(req_url_with_params.clone())
-        .header(reqwest::header::HOST, host)
-        .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8")
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<dart::StatementRes>()
-        .await?;
+    let mut res = metrics::send(
+        Upstream::OpenDart,
+        web_client
+            .get(req_url_with_params.clone())
+            .header(reqwest::header::HOST, host)
+            .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8"),
+    )
+    .await?
+    .json::<dart::StatementRes>()
+    .await?;
 
     // status 013 means data NOT_FOUND
     if res.status == "013" {
@@ -227,15 +238,16 @@ pub async fn get_statement(
         )
         .unwrap();
 
-        res = web_client
-            .get(req_url_with_params)
-            .header(reqwest::header::HOST, host)
-            .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8")
-            .send()
-            .await?
-            .error_for_status()?
-            .json::<dart::StatementRes>()
-            .await?;
+        res = metrics::send(
+            Upstream::OpenDart,
+            web_client
+                .get(req_url_with_params)
+                .header(reqwest::header::HOST, host)
+                .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8"),
+        )
+        .await?
+        .json::<dart::StatementRes>()
+        .await?;
     }
 
     Ok(res)
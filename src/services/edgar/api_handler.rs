@@ -1,8 +1,7 @@
 use super::provider;
-use crate::model::edgar;
-use crate::utils::{cache, error::Error, Result};
+use crate::utils::{Result, cache, error::Error};
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::get("/edgar/{cik}")]
 pub async fn handler_get(
     req: actix_web::HttpRequest,
@@ -13,15 +12,12 @@ pub async fn handler_get(
         return Err(Error::E400BadRequest("invalid CIK".into()));
     }
 
-    // cache first
-    if let Some(data_in_cache) = cache::get::<Option<edgar::StatementRes>>(req.path()).await? {
-        return Ok(actix_web::HttpResponse::Ok().json(data_in_cache));
-    };
-
-    let res = provider::get_statement(&cik).await?;
-
-    // Store in cache
-    cache::set(req.path(), &res).await?;
+    // cache first; concurrent requests for the same path share one fetch
+    let cik = cik.into_inner();
+    let res = cache::get_or_fetch_json(req.path(), cache::policy("edgar"), move || async move {
+        provider::get_statement(&cik).await
+    })
+    .await?;
 
     // Return result
     Ok(actix_web::HttpResponse::Ok().json(res))
@@ -1,5 +1,9 @@
 use crate::model::{edgar, xbrl};
-use crate::utils::{Result, settings::Settings};
+use crate::utils::{
+    Result,
+    metrics::{self, Upstream},
+    settings::Settings,
+};
 
 pub async fn get_statement(cik: &str) -> Result<edgar::StatementRes> {
     let web_client = reqwest::Client::new();
@@ -10,17 +14,18 @@ pub async fn get_statement(cik: &str) -> Result<edgar::StatementRes> {
     let host = req_url.host_str().unwrap();
 
     // Get a list of edgar reports from the internet
-    let res = web_client
-        .get(req_url.clone())
-        .header(reqwest::header::HOST, host)
-        .header(reqwest::header::USER_AGENT, &agent)
-        .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8")
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<edgar::Submissions>()
-        .await
-        .expect("Valid edgar submission response");
+    let res = metrics::send(
+        Upstream::Sec,
+        web_client
+            .get(req_url.clone())
+            .header(reqwest::header::HOST, host)
+            .header(reqwest::header::USER_AGENT, &agent)
+            .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8"),
+    )
+    .await?
+    .json::<edgar::Submissions>()
+    .await
+    .expect("Valid edgar submission response");
 
     // Find index of the latest annual report
     let mut index = 0;
@@ -43,16 +48,17 @@ pub async fn get_statement(cik: &str) -> Result<edgar::StatementRes> {
     let req_url = reqwest::Url::parse(&url).unwrap();
     let host = req_url.host_str().unwrap();
 
-    let res = web_client
-        .get(req_url.clone())
-        .header(reqwest::header::HOST, host)
-        .header(reqwest::header::USER_AGENT, &agent)
-        .header(reqwest::header::ACCEPT, "application/xml;charset=UTF-8")
-        .send()
-        .await?
-        .error_for_status()?
-        .text()
-        .await?;
+    let res = metrics::send(
+        Upstream::Sec,
+        web_client
+            .get(req_url.clone())
+            .header(reqwest::header::HOST, host)
+            .header(reqwest::header::USER_AGENT, &agent)
+            .header(reqwest::header::ACCEPT, "application/xml;charset=UTF-8"),
+    )
+    .await?
+    .text()
+    .await?;
 
     // Parse report to extract data
     let doc = roxmltree::Document::parse(&res)?;
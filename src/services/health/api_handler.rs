@@ -0,0 +1,24 @@
+use super::provider;
+use crate::utils::Result;
+
+/// Liveness: the process is up and serving requests
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::get("/healthz")]
+pub async fn handler_get_health(req: actix_web::HttpRequest) -> Result<actix_web::HttpResponse> {
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().json(serde_json::json!({ "status": "ok" })))
+}
+
+/// Readiness: DB and settings are usable; otherwise `503` with the failing dependency
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::get("/readyz")]
+pub async fn handler_get_ready(req: actix_web::HttpRequest) -> Result<actix_web::HttpResponse> {
+    let res = provider::get_readiness().await;
+
+    // Return result
+    if res.ready {
+        Ok(actix_web::HttpResponse::Ok().json(res))
+    } else {
+        Ok(actix_web::HttpResponse::ServiceUnavailable().json(res))
+    }
+}
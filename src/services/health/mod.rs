@@ -0,0 +1,4 @@
+mod api_handler;
+mod provider;
+
+pub use api_handler::*;
@@ -0,0 +1,65 @@
+use crate::model::{DependencyRes, ReadinessRes};
+use crate::utils::{Result, cache, db, error::Error, settings::Settings};
+
+// A dependency slower than this is as good as down for a request
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[tracing::instrument]
+pub async fn get_readiness() -> ReadinessRes {
+    let (db, cache, settings) = tokio::join!(
+        check(ping_db()),
+        check(cache::ping()),
+        check(async { check_settings() })
+    );
+
+    // Redis is optional: without it responses are only slower
+    ReadinessRes {
+        ready: db.ok && settings.ok,
+        degraded: !cache.ok,
+        db,
+        cache,
+        settings,
+    }
+}
+
+async fn check(f: impl Future<Output = Result<()>>) -> DependencyRes {
+    let start = std::time::Instant::now();
+    let res = match tokio::time::timeout(TIMEOUT, f).await {
+        Ok(r) => r,
+        Err(_) => Err(Error::General(format!("timed out after {:?}", TIMEOUT))),
+    };
+
+    DependencyRes {
+        ok: res.is_ok(),
+        latency_ms: start.elapsed().as_millis(),
+        error: res.err().map(|e| e.to_string()),
+    }
+}
+
+async fn ping_db() -> Result<()> {
+    let client = db::pool().get().await?;
+    client.simple_query("SELECT 1").await?;
+    Ok(())
+}
+
+fn check_settings() -> Result<()> {
+    let app_settings = Settings::instance();
+    let required = [
+        ("keys.data_go_kr", &app_settings.keys.data_go_kr),
+        ("keys.dart", &app_settings.keys.dart),
+        ("keys.krx_id", &app_settings.keys.krx_id),
+        ("keys.krx_pw", &app_settings.keys.krx_pw),
+        ("agent.common", &app_settings.agent.common),
+        ("agent.sec_gov", &app_settings.agent.sec_gov),
+    ];
+
+    let missing: Vec<&str> = required
+        .iter()
+        .filter(|(_, v)| v.trim().is_empty())
+        .map(|(k, _)| *k)
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::General(format!("missing {}", missing.join(", "))));
+    }
+    Ok(())
+}
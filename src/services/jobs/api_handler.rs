@@ -0,0 +1,49 @@
+use super::{provider, queue};
+use crate::model::JobRes;
+use crate::utils::{Result, error::Error};
+
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::get("/jobs")]
+pub async fn handler_get(req: actix_web::HttpRequest) -> Result<actix_web::HttpResponse> {
+    let res = provider::get_scheduled_jobs().await?;
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().json(res))
+}
+
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::get("/jobs/{id}")]
+pub async fn handler_get_one(
+    req: actix_web::HttpRequest,
+    id: actix_web::web::Path<String>,
+) -> Result<actix_web::HttpResponse> {
+    let id =
+        uuid::Uuid::parse_str(&id).map_err(|_| Error::E400BadRequest("invalid job id".into()))?;
+
+    let res = provider::get_job(&id).await?;
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().json(res))
+}
+
+/// Rebuild weekly prices of every stored symbol, e.g. after changing `prices.week_start`
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::post("/jobs/rebuild_weekly")]
+pub async fn handler_post_rebuild_weekly(
+    req: actix_web::HttpRequest,
+) -> Result<actix_web::HttpResponse> {
+    let job = queue::enqueue(queue::JobKind::RebuildWeekly).await?;
+
+    // Return result
+    Ok(accepted(&job))
+}
+
+/// Respond with `202 Accepted` pointing to where the job can be polled
+pub fn accepted(job: &JobRes) -> actix_web::HttpResponse {
+    actix_web::HttpResponse::Accepted()
+        .insert_header((
+            actix_web::http::header::LOCATION,
+            format!("/v1/jobs/{}", job.id),
+        ))
+        .json(job)
+}
@@ -0,0 +1,82 @@
+use super::freshness::last_published_day;
+use crate::utils::{Result, calendar::Market, db, settings::Settings};
+
+/// Range of a checkpointed backfill of one symbol
+#[derive(Debug)]
+pub struct Backfill {
+    market: Market,
+    symbol: String,
+    pub start: time::Date,
+    pub resumed: Option<time::Date>, // where an interrupted backfill left off
+    pub end: time::Date,             // sessions after it may not be published yet
+}
+
+impl Backfill {
+    /// Resume from the checkpoint of `symbol`, or start over when the start date has been changed since
+    #[tracing::instrument(err)]
+    pub async fn load(market: Market, symbol: &str) -> Result<Self> {
+        let (sql, _, _) = Self::sql(market);
+        let settings = &Settings::instance().backfill;
+        let start = match market {
+            Market::Krx => settings.krx_start,
+            Market::Us => settings.us_start,
+        };
+
+        let rows = db::query(sql, &[&symbol]).await?;
+        let resumed = rows
+            .first()
+            .filter(|row| row.get::<_, time::Date>("start_date") == start)
+            .map(|row| row.get("next_date"));
+
+        Ok(Self {
+            market,
+            symbol: symbol.to_string(),
+            start,
+            resumed,
+            end: last_published_day(market),
+        })
+    }
+
+    /// Record that everything before `next` is stored, along with what was stored
+    pub async fn save(
+        &self,
+        transaction: &deadpool_postgres::Transaction<'_>,
+        next: time::Date,
+    ) -> Result<()> {
+        let (_, sql, _) = Self::sql(self.market);
+        transaction
+            .execute(sql, &[&self.symbol, &self.start, &next])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self) -> Result<()> {
+        let (_, _, sql) = Self::sql(self.market);
+        db::query(sql, &[&self.symbol]).await?;
+        Ok(())
+    }
+
+    // Queries to read, save and delete a checkpoint
+    fn sql(market: Market) -> (&'static str, &'static str, &'static str) {
+        match market {
+            Market::Krx => (
+                "SELECT start_date, next_date FROM price_backfill WHERE srtn_cd=$1::CHAR(6);",
+                "
+                INSERT INTO price_backfill(srtn_cd,start_date,next_date,updated)
+                VALUES ($1::CHAR(6),$2::DATE,$3::DATE,NOW())
+                ON CONFLICT (srtn_cd) DO UPDATE
+                SET start_date=EXCLUDED.start_date, next_date=EXCLUDED.next_date, updated=NOW();",
+                "DELETE FROM price_backfill WHERE srtn_cd=$1::CHAR(6);",
+            ),
+            Market::Us => (
+                "SELECT start_date, next_date FROM price_us_backfill WHERE ticker=$1::VARCHAR(10);",
+                "
+                INSERT INTO price_us_backfill(ticker,start_date,next_date,updated)
+                VALUES ($1::VARCHAR(10),$2::DATE,$3::DATE,NOW())
+                ON CONFLICT (ticker) DO UPDATE
+                SET start_date=EXCLUDED.start_date, next_date=EXCLUDED.next_date, updated=NOW();",
+                "DELETE FROM price_us_backfill WHERE ticker=$1::VARCHAR(10);",
+            ),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use super::queue::{self, JobKind};
+use crate::utils::{
+    Result, cache,
+    calendar::Market,
+    db,
+    settings::{RefreshMode, Settings},
+};
+use tracing::{Level, event};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Freshness {
+    Fresh,
+    Refreshed, // stored data has just been updated; query it again
+    Stale,     // a refresh is pending or the upstream has nothing newer yet
+}
+
+/// The last session the upstream should have published by now, allowing for its lag
+pub fn last_published_day(market: Market) -> time::Date {
+    let settings = &Settings::instance().freshness;
+    let lag = match market {
+        Market::Krx => settings.krx_lag_days,
+        Market::Us => settings.us_lag_days,
+    };
+
+    let mut day = market.last_completed_trading_day(time::OffsetDateTime::now_utc());
+    for _ in 0..lag {
+        day = market.previous_trading_day(day);
+    }
+    day
+}
+
+/// Compare the latest stored date of `symbol` against the market; see `check_freshness`
+pub async fn refresh_if_behind(market: Market, symbol: &str) -> Result<Freshness> {
+    let (sql, kind) = match market {
+        Market::Krx => (
+            "SELECT MAX(bas_dt) FROM price WHERE srtn_cd=$1::CHAR(6);",
+            JobKind::UpdatePrices(symbol.to_string()),
+        ),
+        Market::Us => (
+            "SELECT MAX(date) FROM price_us WHERE ticker=$1::VARCHAR(10);",
+            JobKind::UpdatePricesUs(symbol.to_string()),
+        ),
+    };
+
+    let rows = db::query(sql, &[&symbol]).await?;
+    let Some(latest) = rows[0].get::<_, Option<time::Date>>("max") else {
+        return Ok(Freshness::Fresh);
+    };
+    Ok(check_freshness(market, latest, kind).await)
+}
+
+/// Compare the `latest` stored date against the last session the upstream should have published
+/// and refresh through `kind` as set in `Settings.freshness`, at most once per throttle period
+#[tracing::instrument]
+async fn check_freshness(market: Market, latest: time::Date, kind: JobKind) -> Freshness {
+    let settings = &Settings::instance().freshness;
+    if !settings.enabled {
+        return Freshness::Fresh;
+    }
+
+    if latest >= last_published_day(market) {
+        return Freshness::Fresh;
+    }
+
+    let key = format!(
+        "refresh:{}:{}",
+        kind.name(),
+        kind.target().unwrap_or_default()
+    );
+    if !cache::throttle(&key, settings.throttle_secs).await {
+        return Freshness::Stale;
+    }
+
+    // Failing to refresh should not fail the read; what is stored is still served
+    match settings.mode {
+        RefreshMode::Inline => match queue::perform(&kind).await {
+            Ok(_) => Freshness::Refreshed,
+            Err(e) => {
+                event!(Level::WARN, "failed to refresh {}: {}", key, e);
+                Freshness::Stale
+            }
+        },
+        RefreshMode::Background => {
+            if let Err(e) = queue::enqueue(kind).await {
+                event!(Level::WARN, "failed to enqueue {}: {}", key, e);
+            }
+            Freshness::Stale
+        }
+    }
+}
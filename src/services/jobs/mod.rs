@@ -0,0 +1,12 @@
+mod api_handler;
+mod backfill;
+mod freshness;
+mod provider;
+mod queue;
+mod scheduler;
+
+pub use api_handler::*;
+pub use backfill::Backfill;
+pub use freshness::{Freshness, refresh_if_behind};
+pub use queue::{JobKind, enqueue, report_progress, spawn_workers};
+pub use scheduler::spawn_scheduler;
@@ -0,0 +1,179 @@
+use super::scheduler;
+use crate::model::{JobRes, ScheduledJobRes};
+use crate::utils::{Result, db, error::Error, settings::Settings};
+
+#[tracing::instrument(err)]
+pub async fn get_scheduled_jobs() -> Result<Vec<ScheduledJobRes>> {
+    const SQL: &str = "SELECT * FROM scheduled_job;";
+
+    let rows = db::query(SQL, &[]).await?;
+    let settings = &Settings::instance().scheduler;
+
+    let res = settings
+        .jobs
+        .iter()
+        .map(|(name, job)| {
+            let row = rows.iter().find(|row| row.get::<_, &str>("name") == name);
+            let next_run = if settings.enabled && job.enabled {
+                scheduler::next_run(&job.cron)
+            } else {
+                None
+            };
+            ScheduledJobRes {
+                name: name.clone(),
+                enabled: settings.enabled && job.enabled,
+                cron: job.cron.clone(),
+                next_run,
+                last_run: row.and_then(|r| r.get("last_run")),
+                last_success: row.and_then(|r| r.get("last_success")),
+                last_error: row.and_then(|r| r.get("last_error")),
+                last_error_at: row.and_then(|r| r.get("last_error_at")),
+            }
+        })
+        .collect();
+
+    Ok(res)
+}
+
+#[tracing::instrument(err)]
+/// Record the run of `name` due at `tick`; `false` if another instance already has
+pub async fn claim_run(name: &str, tick: time::OffsetDateTime) -> Result<bool> {
+    const SQL: &str = "
+        INSERT INTO scheduled_job(name, last_run) VALUES ($1::VARCHAR(40), $2::TIMESTAMPTZ)
+        ON CONFLICT (name) DO UPDATE SET last_run = EXCLUDED.last_run
+        WHERE scheduled_job.last_run IS NULL OR scheduled_job.last_run < EXCLUDED.last_run
+        RETURNING name;";
+
+    let rows = db::query(SQL, &[&name, &tick]).await?;
+    Ok(!rows.is_empty())
+}
+
+#[tracing::instrument(err)]
+pub async fn record_success(name: &str) -> Result<()> {
+    const SQL: &str = "
+        INSERT INTO scheduled_job(name, last_success) VALUES ($1::VARCHAR(40), NOW())
+        ON CONFLICT (name) DO UPDATE SET last_success = EXCLUDED.last_success;";
+
+    db::query(SQL, &[&name]).await?;
+    Ok(())
+}
+
+#[tracing::instrument(err)]
+pub async fn record_error(name: &str, message: &str) -> Result<()> {
+    const SQL: &str = "
+        INSERT INTO scheduled_job(name, last_error, last_error_at)
+        VALUES ($1::VARCHAR(40), $2::TEXT, NOW())
+        ON CONFLICT (name) DO UPDATE
+        SET last_error = EXCLUDED.last_error, last_error_at = EXCLUDED.last_error_at;";
+
+    db::query(SQL, &[&name, &message]).await?;
+    Ok(())
+}
+
+#[tracing::instrument(err)]
+pub async fn insert_job(kind: &str, target: Option<&str>) -> Result<JobRes> {
+    const SQL: &str = "
+        INSERT INTO job(id, kind, target, state)
+        VALUES ($1::UUID, $2::VARCHAR(40), $3::VARCHAR(40), 'queued')
+        RETURNING *;";
+
+    let id = uuid::Uuid::new_v4();
+    let rows = db::query(SQL, &[&id, &kind, &target]).await?;
+
+    Ok(JobRes::from(&rows[0]))
+}
+
+#[tracing::instrument(err)]
+pub async fn get_job(id: &uuid::Uuid) -> Result<JobRes> {
+    const SQL: &str = "SELECT * FROM job WHERE id=$1::UUID;";
+
+    let rows = db::query(SQL, &[id]).await?;
+    if rows.is_empty() {
+        return Err(Error::E404NotFound("job".into()));
+    }
+
+    Ok(JobRes::from(&rows[0]))
+}
+
+#[tracing::instrument(err)]
+pub async fn claim_next_job(owner: &uuid::Uuid) -> Result<Option<JobRes>> {
+    // SKIP LOCKED lets several workers poll the same table without picking the same job
+    const SQL: &str = "
+        UPDATE job SET state = 'running', started_at = NOW(), owner = $1::UUID, heartbeat_at = NOW()
+        WHERE id = (
+            SELECT id FROM job WHERE state = 'queued'
+            ORDER BY created_at
+            FOR UPDATE SKIP LOCKED
+            LIMIT 1)
+        RETURNING *;";
+
+    let rows = db::query(SQL, &[owner]).await?;
+
+    Ok(rows.first().map(JobRes::from))
+}
+
+#[tracing::instrument(err)]
+pub async fn update_job_progress(id: &uuid::Uuid, done: i32, total: i32) -> Result<()> {
+    const SQL: &str = "
+        UPDATE job SET done = $2::INTEGER, total = $3::INTEGER, heartbeat_at = NOW()
+        WHERE id=$1::UUID;";
+
+    db::query(SQL, &[id, &done, &total]).await?;
+    Ok(())
+}
+
+/// Show that the owner is still running the job
+#[tracing::instrument(err)]
+pub async fn touch_job(id: &uuid::Uuid, owner: &uuid::Uuid) -> Result<()> {
+    const SQL: &str = "
+        UPDATE job SET heartbeat_at = NOW()
+        WHERE id=$1::UUID AND owner=$2::UUID AND state = 'running';";
+
+    db::query(SQL, &[id, owner]).await?;
+    Ok(())
+}
+
+/// Finish a job unless it has been handed to another owner in the meantime
+#[tracing::instrument(err)]
+pub async fn finish_job(
+    id: &uuid::Uuid,
+    owner: &uuid::Uuid,
+    error: Option<&str>,
+    result: Option<&str>,
+) -> Result<()> {
+    const SQL: &str = "
+        UPDATE job SET
+            state = CASE WHEN $3::TEXT IS NULL THEN 'succeeded' ELSE 'failed' END,
+            error = $3::TEXT,
+            result = $4::TEXT,
+            done = CASE WHEN $3::TEXT IS NULL THEN total ELSE done END,
+            finished_at = NOW()
+        WHERE id=$1::UUID AND owner=$2::UUID AND state = 'running';";
+
+    db::query(SQL, &[id, owner, &error, &result]).await?;
+    Ok(())
+}
+
+/// Queue again the running jobs whose owner has not shown a sign of life for `stale_secs`
+#[tracing::instrument(err)]
+pub async fn requeue_stale_jobs(stale_secs: u64) -> Result<u64> {
+    const SQL: &str = "
+        UPDATE job SET state = 'queued', started_at = NULL, owner = NULL, heartbeat_at = NULL
+        WHERE state = 'running'
+            AND COALESCE(heartbeat_at, started_at, created_at)
+                < NOW() - make_interval(secs => $1::DOUBLE PRECISION);";
+
+    let db_client = db::pool().get().await?;
+    Ok(db_client.execute(SQL, &[&(stale_secs as f64)]).await?)
+}
+
+/// Remove jobs that finished more than `days` ago
+#[tracing::instrument(err)]
+pub async fn delete_finished_jobs(days: u32) -> Result<u64> {
+    const SQL: &str = "
+        DELETE FROM job
+        WHERE finished_at < NOW() - make_interval(days => $1::INTEGER);";
+
+    let db_client = db::pool().get().await?;
+    Ok(db_client.execute(SQL, &[&(days as i32)]).await?)
+}
@@ -0,0 +1,252 @@
+use super::provider;
+use crate::model::JobRes;
+use crate::services::{companies, prices, prices_us, tickers};
+use crate::utils::{
+    Result,
+    error::Error,
+    settings::{Jobs as JobsSettings, Settings},
+};
+use tracing::{Level, event};
+
+static NOTIFY: tokio::sync::Notify = tokio::sync::Notify::const_new();
+
+// Identifies this process as the owner of the jobs it runs
+static OWNER: std::sync::LazyLock<uuid::Uuid> = std::sync::LazyLock::new(uuid::Uuid::new_v4);
+
+tokio::task_local! {
+    // Job run by the current task, if any
+    static CURRENT: uuid::Uuid;
+}
+
+/// Work that can be run in the background; persisted as `job.kind` and `job.target`
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobKind {
+    BuildPrices(String),
+    BuildPricesUs(String),
+    UpdatePrices(String),
+    UpdatePricesUs(String),
+    BuildTickers,
+    BuildCompanies,
+    RebuildPrices, // derived tables of every stored symbol
+    RebuildPricesUs,
+    RebuildWeekly,      // every stored symbol of both markets
+    UpdatePricesMarket, // every listed KR company
+}
+
+impl JobKind {
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            Self::BuildPrices(_) => "build_prices",
+            Self::BuildPricesUs(_) => "build_prices_us",
+            Self::UpdatePrices(_) => "update_prices",
+            Self::UpdatePricesUs(_) => "update_prices_us",
+            Self::BuildTickers => "build_tickers",
+            Self::BuildCompanies => "build_companies",
+            Self::RebuildPrices => "rebuild_prices",
+            Self::RebuildPricesUs => "rebuild_prices_us",
+            Self::RebuildWeekly => "rebuild_weekly",
+            Self::UpdatePricesMarket => "update_prices_market",
+        }
+    }
+
+    pub(super) fn target(&self) -> Option<&str> {
+        match self {
+            Self::BuildPrices(code) | Self::UpdatePrices(code) => Some(code),
+            Self::BuildPricesUs(ticker) | Self::UpdatePricesUs(ticker) => Some(ticker),
+            Self::BuildTickers
+            | Self::BuildCompanies
+            | Self::RebuildPrices
+            | Self::RebuildPricesUs
+            | Self::RebuildWeekly
+            | Self::UpdatePricesMarket => None,
+        }
+    }
+
+    fn from_parts(name: &str, target: Option<String>) -> Result<Self> {
+        match (name, target) {
+            ("build_prices", Some(code)) => Ok(Self::BuildPrices(code)),
+            ("build_prices_us", Some(ticker)) => Ok(Self::BuildPricesUs(ticker)),
+            ("update_prices", Some(code)) => Ok(Self::UpdatePrices(code)),
+            ("update_prices_us", Some(ticker)) => Ok(Self::UpdatePricesUs(ticker)),
+            ("build_tickers", _) => Ok(Self::BuildTickers),
+            ("build_companies", _) => Ok(Self::BuildCompanies),
+            ("rebuild_prices", _) => Ok(Self::RebuildPrices),
+            ("rebuild_prices_us", _) => Ok(Self::RebuildPricesUs),
+            ("rebuild_weekly", _) => Ok(Self::RebuildWeekly),
+            ("update_prices_market", _) => Ok(Self::UpdatePricesMarket),
+            (name, target) => Err(Error::General(format!(
+                "unknown job: {} ({:?})",
+                name, target
+            ))),
+        }
+    }
+}
+
+/// Persist a job and wake up a worker to run it
+#[tracing::instrument(err)]
+pub async fn enqueue(kind: JobKind) -> Result<JobRes> {
+    let job = provider::insert_job(kind.name(), kind.target()).await?;
+    NOTIFY.notify_one();
+    Ok(job)
+}
+
+/// Spawn workers configured in `Settings.jobs`; jobs abandoned by a stopped process are queued again
+pub fn spawn_workers() {
+    let settings = Settings::instance().jobs.clone();
+
+    tokio::spawn(maintain_forever(settings.clone()));
+
+    let interval = std::time::Duration::from_secs(settings.poll_interval_secs);
+    for _ in 0..settings.workers {
+        tokio::spawn(work_forever(interval));
+    }
+}
+
+/// Requeue stale jobs and remove old ones; a job of another live process is never touched
+async fn maintain_forever(settings: JobsSettings) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+        settings.heartbeat_secs.max(1),
+    ));
+    loop {
+        interval.tick().await;
+        if let Ok(n) = provider::requeue_stale_jobs(settings.stale_secs).await
+            && n > 0
+        {
+            event!(Level::INFO, "requeued {} stale jobs", n);
+            NOTIFY.notify_waiters();
+        }
+        if let Ok(n) = provider::delete_finished_jobs(settings.retention_days).await
+            && n > 0
+        {
+            event!(Level::INFO, "removed {} finished jobs", n);
+        }
+    }
+}
+
+async fn work_forever(interval: std::time::Duration) {
+    loop {
+        match provider::claim_next_job(&OWNER).await {
+            Ok(Some(job)) => run(job).await,
+            Ok(None) => {
+                // Poll as well so that jobs enqueued by another instance get picked up
+                let _ = tokio::time::timeout(interval, NOTIFY.notified()).await;
+            }
+            Err(_) => tokio::time::sleep(interval).await,
+        }
+    }
+}
+
+#[tracing::instrument(skip_all, fields(id = %job.id, kind = %job.kind))]
+async fn run(job: JobRes) {
+    let heartbeat = tokio::spawn(heartbeat_forever(job.id));
+    let result = match JobKind::from_parts(&job.kind, job.target.clone()) {
+        // Run on its own task so that a panic fails the job instead of killing the worker
+        Ok(kind) => tokio::spawn(CURRENT.scope(job.id, execute(job.id, kind)))
+            .await
+            .map_err(Error::from)
+            .and_then(|r| r),
+        Err(e) => Err(e),
+    };
+    heartbeat.abort();
+
+    let (error, report) = match result {
+        Ok(report) => (None, report.map(|v| v.to_string())),
+        Err(e) => (Some(e.to_string()), None),
+    };
+    let _ = provider::finish_job(&job.id, &OWNER, error.as_deref(), report.as_deref()).await;
+}
+
+async fn heartbeat_forever(id: uuid::Uuid) {
+    let secs = Settings::instance().jobs.heartbeat_secs.max(1);
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(secs));
+    loop {
+        interval.tick().await;
+        let _ = provider::touch_job(&id, &OWNER).await;
+    }
+}
+
+async fn execute(id: uuid::Uuid, kind: JobKind) -> Result<Option<serde_json::Value>> {
+    // Jobs that do not report their steps go from 0/1 straight to done
+    provider::update_job_progress(&id, 0, 1).await?;
+    perform(&kind).await
+}
+
+/// Record how much of the job running on this task is done; does nothing outside a job
+pub async fn report_progress(done: usize, total: usize) {
+    let Ok(id) = CURRENT.try_with(|id| *id) else {
+        return;
+    };
+    let done = i32::try_from(done).unwrap_or(i32::MAX);
+    let total = i32::try_from(total).unwrap_or(i32::MAX);
+    let _ = provider::update_job_progress(&id, done, total).await;
+}
+
+/// Do the work of a job right away, without going through the queue; some jobs report a result
+pub(super) async fn perform(kind: &JobKind) -> Result<Option<serde_json::Value>> {
+    match kind {
+        JobKind::BuildPrices(code) => {
+            let res = prices::provider::build_price_db(code).await?;
+            return Ok(Some(serde_json::to_value(res)?));
+        }
+        JobKind::BuildPricesUs(ticker) => {
+            let res = prices_us::provider::build_price_db(ticker).await?;
+            return Ok(Some(serde_json::to_value(res)?));
+        }
+        JobKind::UpdatePrices(code) => prices::provider::update_price_db(code).await?,
+        JobKind::UpdatePricesUs(ticker) => prices_us::provider::update_price_db(ticker).await?,
+        JobKind::BuildTickers => {
+            let res = tickers::provider::build_ticker_db().await?;
+            return Ok(Some(serde_json::to_value(res)?));
+        }
+        JobKind::BuildCompanies => {
+            let res = companies::provider::build_company_db().await?;
+            return Ok(Some(serde_json::to_value(res)?));
+        }
+        JobKind::RebuildPrices => {
+            let res = prices::provider::rebuild_derived_price_all(None).await?;
+            return Ok(Some(serde_json::to_value(res)?));
+        }
+        JobKind::RebuildPricesUs => {
+            let res = prices_us::provider::rebuild_derived_price_all(None).await?;
+            return Ok(Some(serde_json::to_value(res)?));
+        }
+        JobKind::RebuildWeekly => {
+            let prices = prices::provider::rebuild_derived_price_all(None).await?;
+            let prices_us = prices_us::provider::rebuild_derived_price_all(None).await?;
+            return Ok(Some(
+                serde_json::json!({ "prices": prices, "prices_us": prices_us }),
+            ));
+        }
+        JobKind::UpdatePricesMarket => {
+            let res = prices::provider::update_market_price_db().await?;
+            return Ok(Some(serde_json::to_value(res)?));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn job_kind_round_trip() {
+        let kinds = [
+            JobKind::BuildPrices("005930".into()),
+            JobKind::BuildPricesUs("AAPL".into()),
+            JobKind::UpdatePrices("005930".into()),
+            JobKind::UpdatePricesUs("AAPL".into()),
+            JobKind::BuildTickers,
+            JobKind::BuildCompanies,
+            JobKind::RebuildPrices,
+            JobKind::RebuildPricesUs,
+            JobKind::RebuildWeekly,
+            JobKind::UpdatePricesMarket,
+        ];
+        for kind in kinds {
+            let parsed = JobKind::from_parts(kind.name(), kind.target().map(String::from)).unwrap();
+            assert_eq!(parsed, kind);
+        }
+        assert!(JobKind::from_parts("build_prices", None).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+use super::provider;
+use crate::services::{companies, dart, prices, prices_us, tickers};
+use crate::utils::{Result, error::Error, settings::Settings};
+use std::str::FromStr;
+use tracing::{Level, event};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Task {
+    Prices,
+    PricesUs,
+    PricesMarket,
+    Tickers,
+    Companies,
+    DartCodes,
+}
+
+impl FromStr for Task {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "prices" => Ok(Self::Prices),
+            "prices_us" => Ok(Self::PricesUs),
+            "prices_market" => Ok(Self::PricesMarket),
+            "tickers" => Ok(Self::Tickers),
+            "companies" => Ok(Self::Companies),
+            "dart_codes" => Ok(Self::DartCodes),
+            _ => Err(Error::General(format!("unknown scheduled job: {}", s))),
+        }
+    }
+}
+
+/// Spawn a background task for every enabled job in `Settings.scheduler`
+pub fn spawn_scheduler() {
+    let settings = &Settings::instance().scheduler;
+    if !settings.enabled {
+        event!(Level::INFO, "scheduler is disabled");
+        return;
+    }
+
+    // Checked when the settings are loaded
+    let offset = match settings.offset() {
+        Ok(offset) => offset,
+        Err(e) => {
+            event!(Level::ERROR, "{}", e);
+            return;
+        }
+    };
+
+    for (name, job) in &settings.jobs {
+        if !job.enabled {
+            continue;
+        }
+
+        let task = match Task::from_str(name) {
+            Ok(task) => task,
+            Err(e) => {
+                event!(Level::ERROR, "{}", e);
+                continue;
+            }
+        };
+
+        let schedule = match cron::Schedule::from_str(&job.cron) {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                event!(Level::ERROR, "invalid cron for job {}: {}", name, e);
+                continue;
+            }
+        };
+
+        event!(Level::INFO, "scheduling job {} with [{}]", name, job.cron);
+        tokio::spawn(run_forever(name.clone(), task, schedule, offset));
+    }
+}
+
+/// Next fire time of the cron expression, evaluated in the configured UTC offset
+pub fn next_run(cron: &str) -> Option<time::OffsetDateTime> {
+    let schedule = cron::Schedule::from_str(cron).ok()?;
+    let offset = Settings::instance().scheduler.offset().ok()?;
+    next_after_now(&schedule, offset)
+}
+
+fn next_after_now(
+    schedule: &cron::Schedule,
+    offset: chrono::FixedOffset,
+) -> Option<time::OffsetDateTime> {
+    let now = chrono::Utc::now().with_timezone(&offset);
+    let next = schedule.after(&now).next()?;
+    time::OffsetDateTime::from_unix_timestamp(next.timestamp()).ok()
+}
+
+async fn run_forever(
+    name: String,
+    task: Task,
+    schedule: cron::Schedule,
+    offset: chrono::FixedOffset,
+) {
+    // Recompute from now on every loop so that a long run never fires back-to-back
+    while let Some(next) = next_after_now(&schedule, offset) {
+        let wait = next - time::OffsetDateTime::now_utc();
+        tokio::time::sleep(wait.try_into().unwrap_or_default()).await;
+        run(&name, task, next).await;
+    }
+}
+
+#[tracing::instrument]
+async fn run(name: &str, task: Task, tick: time::OffsetDateTime) {
+    // Every instance fires on the same tick; only the first to record it runs the job
+    match provider::claim_run(name, tick).await {
+        Ok(true) => {}
+        Ok(false) => {
+            event!(Level::INFO, "job {} has run on another instance", name);
+            return;
+        }
+        Err(e) => {
+            event!(Level::ERROR, "failed to record job {}: {}", name, e);
+            return;
+        }
+    }
+
+    let result = match task {
+        Task::Prices => {
+            let codes = prices::provider::get_stored_codes().await;
+            refresh_all(codes, |code| async move {
+                prices::provider::update_price_db(&code).await
+            })
+            .await
+        }
+        Task::PricesUs => {
+            let tickers = prices_us::provider::get_stored_tickers().await;
+            refresh_all(tickers, |ticker| async move {
+                prices_us::provider::update_price_db(&ticker).await
+            })
+            .await
+        }
+        Task::PricesMarket => prices::provider::update_market_price_db().await.map(|_| ()),
+        Task::Tickers => tickers::provider::build_ticker_db().await.map(|_| ()),
+        Task::Companies => companies::provider::build_company_db().await.map(|_| ()),
+        Task::DartCodes => dart::provider::build_code_db().await,
+    };
+
+    let _ = match result {
+        Ok(()) => provider::record_success(name).await,
+        Err(e) => provider::record_error(name, &e.to_string()).await,
+    };
+}
+
+/// Update every symbol and keep going on failures, reporting them all at the end
+async fn refresh_all<F, Fut>(symbols: Result<Vec<String>>, update: F) -> Result<()>
+where
+    F: Fn(String) -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    let symbols = symbols?;
+    let total = symbols.len();
+    let mut failures: Vec<String> = Vec::new();
+
+    for symbol in symbols {
+        if let Err(e) = update(symbol.clone()).await {
+            failures.push(format!("{}: {}", symbol, e));
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::General(format!(
+            "{} of {} symbols failed; {}",
+            failures.len(),
+            total,
+            failures.join("; ")
+        )))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::settings::Scheduler;
+
+    #[test]
+    fn parse_task() {
+        assert_eq!(Task::from_str("prices").unwrap(), Task::Prices);
+        assert_eq!(Task::from_str("dart_codes").unwrap(), Task::DartCodes);
+        assert_eq!(Task::from_str("prices_market").unwrap(), Task::PricesMarket);
+        assert!(Task::from_str("unknown").is_err());
+    }
+
+    #[test]
+    fn parse_offset() {
+        let scheduler = |utc_offset: Option<&str>| Scheduler {
+            utc_offset: utc_offset.map(String::from),
+            ..Default::default()
+        };
+        assert_eq!(
+            scheduler(Some("+09:00")).offset().unwrap(),
+            chrono::FixedOffset::east_opt(9 * 3600).unwrap()
+        );
+        assert_eq!(
+            scheduler(None).offset().unwrap(),
+            chrono::FixedOffset::east_opt(0).unwrap()
+        );
+        assert!(scheduler(Some("KST")).offset().is_err());
+    }
+
+    #[test]
+    fn parse_cron() {
+        assert!(cron::Schedule::from_str("0 0 14 * * Tue-Sat").is_ok());
+        assert!(cron::Schedule::from_str("every day").is_err());
+    }
+}
@@ -0,0 +1,12 @@
+use crate::utils::{Result, metrics};
+
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::get("/metrics")]
+pub async fn handler_get(req: actix_web::HttpRequest) -> Result<actix_web::HttpResponse> {
+    let res = metrics::gather()?;
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(res))
+}
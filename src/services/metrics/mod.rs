@@ -0,0 +1,3 @@
+mod api_handler;
+
+pub use api_handler::*;
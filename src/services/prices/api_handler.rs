@@ -1,7 +1,16 @@
 use super::provider;
-use crate::utils::{error::Error, Result};
-
-#[tracing::instrument(err)]
+use crate::services::jobs;
+use crate::utils::{
+    Result,
+    datetime::{Period, date_opt_deserialize},
+    error::Error,
+    page::{Page, PageParams},
+    resample::{Bars, BarsParams},
+};
+
+const MAX_REBUILD_SYMBOLS: usize = 100;
+
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::post("/prices/{short_code}")]
 pub async fn handler_post(
     req: actix_web::HttpRequest,
@@ -11,13 +20,62 @@ pub async fn handler_post(
         return Err(Error::E400BadRequest("invalid short_code".into()));
     }
 
-    provider::build_price_db(&short_code).await?;
+    let job = jobs::enqueue(jobs::JobKind::BuildPrices(short_code.into_inner())).await?;
+
+    // Return result
+    Ok(jobs::accepted(&job))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct RebuildReq {
+    symbols: Vec<String>,
+}
+
+/// Rebuild weekly and longer prices from stored daily prices
+///
+/// Listed companies are rebuilt right away; without a body every stored one is rebuilt as a job
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::post("/prices/rebuild")]
+pub async fn handler_post_rebuild(
+    req: actix_web::HttpRequest,
+    body: actix_web::web::Bytes,
+) -> Result<actix_web::HttpResponse> {
+    if body.trim_ascii().is_empty() {
+        let job = jobs::enqueue(jobs::JobKind::RebuildPrices).await?;
+        return Ok(jobs::accepted(&job));
+    }
+
+    // A body that does not parse must not fall back to rebuilding everything
+    let symbols = serde_json::from_slice::<RebuildReq>(&body)
+        .map_err(|e| Error::E400BadRequest(format!("invalid body: {}", e)))?
+        .symbols;
+    if symbols.is_empty() || symbols.len() > MAX_REBUILD_SYMBOLS {
+        return Err(Error::E400BadRequest(format!(
+            "symbols must have 1 to {} entries",
+            MAX_REBUILD_SYMBOLS
+        )));
+    }
+    if symbols.iter().any(|v| v.len() != 6) {
+        return Err(Error::E400BadRequest("invalid short_code".into()));
+    }
+
+    let res = provider::rebuild_derived_price_all(Some(symbols)).await?;
 
     // Return result
-    Ok(actix_web::HttpResponse::Created().finish())
+    Ok(actix_web::HttpResponse::Ok().json(res))
+}
+
+/// Update daily prices of every listed company as a job
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::post("/prices/market")]
+pub async fn handler_post_market(req: actix_web::HttpRequest) -> Result<actix_web::HttpResponse> {
+    let job = jobs::enqueue(jobs::JobKind::UpdatePricesMarket).await?;
+
+    // Return result
+    Ok(jobs::accepted(&job))
 }
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::put("/prices/{short_code}")]
 pub async fn handler_put(
     req: actix_web::HttpRequest,
@@ -33,7 +91,7 @@ pub async fn handler_put(
     Ok(actix_web::HttpResponse::Ok().finish())
 }
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::get("/prices/snapshot")]
 pub async fn handler_get_snapshot(req: actix_web::HttpRequest) -> Result<actix_web::HttpResponse> {
     let res = provider::get_price_all_latest().await?;
@@ -41,7 +99,7 @@ pub async fn handler_get_snapshot(req: actix_web::HttpRequest) -> Result<actix_w
     return Ok(actix_web::HttpResponse::Ok().json(res));
 }
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::get("/prices/{short_code}/latest")]
 pub async fn handler_get_latest(
     req: actix_web::HttpRequest,
@@ -57,39 +115,83 @@ pub async fn handler_get_latest(
     Ok(actix_web::HttpResponse::Ok().json(res))
 }
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::get("/prices/{short_code}/daily")]
 pub async fn handler_get_daily(
     req: actix_web::HttpRequest,
     short_code: actix_web::web::Path<String>,
+    params: actix_web::web::Query<PageParams>,
 ) -> Result<actix_web::HttpResponse> {
     if short_code.len() != 6 {
         return Err(Error::E400BadRequest("invalid short_code".into()));
     }
 
-    let res = provider::get_price_daily(&short_code).await?;
+    let page = Page::try_from(params.into_inner())?;
+    let res = provider::get_price_daily(&short_code, &page).await?;
 
     // Return result
     Ok(actix_web::HttpResponse::Ok().json(res))
 }
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::get("/prices/{short_code}/weekly")]
 pub async fn handler_get_weekly(
     req: actix_web::HttpRequest,
     short_code: actix_web::web::Path<String>,
+    params: actix_web::web::Query<PageParams>,
 ) -> Result<actix_web::HttpResponse> {
     if short_code.len() != 6 {
         return Err(Error::E400BadRequest("invalid short_code".into()));
     }
 
-    let res = provider::get_price_weekly(&short_code).await?;
+    let page = Page::try_from(params.into_inner())?;
+    let res = provider::get_price_weekly(&short_code, &page).await?;
 
     // Return result
     Ok(actix_web::HttpResponse::Ok().json(res))
 }
 
-#[tracing::instrument(err)]
+/// Monthly, quarterly or yearly prices
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::get("/prices/{short_code}/{period:monthly|quarterly|yearly}")]
+pub async fn handler_get_period(
+    req: actix_web::HttpRequest,
+    path: actix_web::web::Path<(String, String)>,
+    params: actix_web::web::Query<PageParams>,
+) -> Result<actix_web::HttpResponse> {
+    let (short_code, period) = path.into_inner();
+    if short_code.len() != 6 {
+        return Err(Error::E400BadRequest("invalid short_code".into()));
+    }
+
+    let period = period.parse::<Period>()?;
+    let page = Page::try_from(params.into_inner())?;
+    let res = provider::get_price_period(&short_code, period, &page).await?;
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().json(res))
+}
+
+/// Bars of any interval resampled from stored daily prices
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::get("/prices/{short_code}/bars")]
+pub async fn handler_get_bars(
+    req: actix_web::HttpRequest,
+    short_code: actix_web::web::Path<String>,
+    params: actix_web::web::Query<BarsParams>,
+) -> Result<actix_web::HttpResponse> {
+    if short_code.len() != 6 {
+        return Err(Error::E400BadRequest("invalid short_code".into()));
+    }
+
+    let bars = Bars::try_from(params.into_inner())?;
+    let res = provider::get_price_bars(&short_code, &bars).await?;
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().json(res))
+}
+
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::get("/prices/{short_code}/exists")]
 pub async fn handler_get_exists(
     req: actix_web::HttpRequest,
@@ -105,7 +207,44 @@ pub async fn handler_get_exists(
     Ok(actix_web::HttpResponse::Ok().json(res))
 }
 
-#[tracing::instrument(err)]
+#[derive(Debug, serde::Deserialize)]
+struct ParamsDelete {
+    #[serde(default, deserialize_with = "date_opt_deserialize")]
+    from: Option<time::Date>,
+    #[serde(default, deserialize_with = "date_opt_deserialize")]
+    to: Option<time::Date>,
+    #[serde(default)]
+    rebuild: bool, // download the history again afterwards
+}
+
+/// Delete stored prices of one symbol, optionally within a date range
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::delete("/prices/{short_code}")]
+pub async fn handler_del_one(
+    req: actix_web::HttpRequest,
+    short_code: actix_web::web::Path<String>,
+    params: actix_web::web::Query<ParamsDelete>,
+) -> Result<actix_web::HttpResponse> {
+    if short_code.len() != 6 {
+        return Err(Error::E400BadRequest("invalid short_code".into()));
+    }
+    if let (Some(from), Some(to)) = (params.from, params.to)
+        && from > to
+    {
+        return Err(Error::E400BadRequest("from is after to".into()));
+    }
+
+    let res = provider::delete_prices(&short_code, params.from, params.to).await?;
+    if params.rebuild {
+        let job = jobs::enqueue(jobs::JobKind::BuildPrices(res.symbol)).await?;
+        return Ok(jobs::accepted(&job));
+    }
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().json(res))
+}
+
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::delete("/prices")]
 pub async fn handler_del(req: actix_web::HttpRequest) -> Result<actix_web::HttpResponse> {
     provider::clear_prices().await?;
@@ -1,4 +1,4 @@
 mod api_handler;
-mod provider;
+pub(crate) mod provider;
 
 pub use api_handler::*;
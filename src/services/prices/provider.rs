@@ -1,24 +1,64 @@
 use crate::model::{
-    StockDayPrice, StockDayPriceRes, StockPrice, StockPriceExistsRes, StockPriceItem,
-    StockWeekPrice, StockWeeklyPriceRes, krx, web,
+    BackfillRes, MarketPriceRes, PriceDeleteRes, RebuildRes, RebuildSymbolRes, StockBar,
+    StockBarsRes, StockDayPrice, StockDayPriceRes, StockPeriodPrice, StockPeriodPriceRes,
+    StockPrice, StockPriceExistsRes, StockPriceItem, StockWeekPrice, StockWeeklyPriceRes, krx, web,
 };
+use crate::services::jobs::{self, Freshness};
 use crate::utils::{
-    Result, cache, datetime::get_sunday_of_week, db, error::Error, settings::Settings,
+    Result, cache,
+    calendar::{self, Market},
+    datetime::{Period, date_chunks},
+    db,
+    error::Error,
+    metrics::{self, Upstream},
+    page::Page,
+    resample::Bars,
+    settings::{CachePolicy, Settings},
 };
 use rust_decimal::prelude::*;
 
-type WeeklyPriceHashMap = std::collections::HashMap<(i32, u8), Vec<StockPriceItem>>;
+type PeriodPriceHashMap<'a> = std::collections::HashMap<(i32, u8), Vec<&'a StockPriceItem>>;
 
+/// Download daily prices since `Settings.backfill.krx_start` in chunks and build what is derived from them
+///
+/// Each chunk is stored along with a checkpoint, so an interrupted build resumes where it stopped
 #[tracing::instrument(err)]
-pub async fn build_price_db(stock_code: &str) -> Result<()> {
-    // Get prices data from web
-    let prices = update_prices_web(stock_code, None).await?;
+pub async fn build_price_db(stock_code: &str) -> Result<BackfillRes> {
+    let backfill = jobs::Backfill::load(Market::Krx, stock_code).await?;
+    let mut res = BackfillRes {
+        symbol: stock_code.to_string(),
+        from: backfill.resumed.unwrap_or(backfill.start),
+        to: backfill.end,
+        chunks: 0,
+        fetched: 0,
+        inserted: 0,
+    };
 
-    // Aggregate prices according to week
-    let map = map_by_week(prices);
+    let chunk_days = Settings::instance().backfill.chunk_days;
+    let chunks = date_chunks(res.from, backfill.end, chunk_days);
+    for (i, &(from, to)) in chunks.iter().enumerate() {
+        jobs::report_progress(i, chunks.len()).await;
+        let prices = fetch_prices_web(stock_code, from, to).await?;
+
+        let mut db_client = db::pool().get().await?;
+        let transaction = db_client.transaction().await?;
+        let codes = insert_prices(&transaction, &prices).await?;
+        backfill.save(&transaction, to.next_day().unwrap()).await?;
+        transaction.commit().await?;
+
+        res.chunks += 1;
+        res.fetched += prices.len();
+        res.inserted += codes.len() as u64;
+    }
 
-    // Update DB
-    update_weekly_price_db(map).await
+    if backfill.resumed.is_none() && res.fetched == 0 {
+        backfill.delete().await?;
+        return Err(Error::E404NotFound("No data found from web".into()));
+    }
+
+    // From the start since an earlier run may have stopped before this step
+    update_period_price_from_db(stock_code, Some(backfill.start), &Period::ALL).await?;
+    Ok(res)
 }
 
 #[tracing::instrument(err)]
@@ -28,41 +68,41 @@ pub async fn update_price_db(stock_code: &str) -> Result<()> {
 
     let rows = db::query(SQL_LATEST_DATE, &[&stock_code]).await?;
     let last_date: Option<time::Date> = rows[0].get("max");
-    let date_from = match last_date {
-        None => None,
-        Some(date) => Some(get_sunday_of_week(&date)?),
+
+    // Nothing new until another trading day has closed
+    let last_trading_day = Market::Krx.last_completed_trading_day(time::OffsetDateTime::now_utc());
+    if last_date.is_some_and(|date| date >= last_trading_day) {
+        return Ok(());
+    }
+
+    let Some(last_date) = last_date else {
+        return build_price_db(stock_code).await.map(|_| ());
     };
+    let date_from = Period::Week.start(&last_date)?;
 
     // Get prices data from web from last week
-    let prices = update_prices_web(stock_code, date_from).await?;
+    let prices = update_prices_web(stock_code, date_from, last_trading_day).await?;
 
     // Aggregate prices according to week
-    let map = map_by_week(prices);
+    let map = map_by_period(&prices, Period::Week);
 
     // Update DB
-    update_weekly_price_db(map).await
+    update_period_price_db(map, Period::Week).await?;
+    update_period_price_from_db(stock_code, Some(date_from), &Period::LONG).await
 }
 
 #[tracing::instrument(err)]
 pub async fn get_price_all_latest() -> Result<krx::ResBody> {
     let cache_key = "dbms/MDC/STAT/standard/MDCSTAT01501";
-    let cache_time = 600; // 10 minutes
 
-    // Check cache first
-    let res = match cache::get::<Option<krx::ResBody>>(cache_key).await? {
-        Some(data) => data,
-        None => {
-            // Fetch current price data of all companies in krx
-            let data = fetch_krx_prices_all().await?;
-
-            // Save data in cache
-            cache::set_with_timer(cache_key, &data, cache_time).await?;
-
-            data
-        }
+    // Cache briefly during the session and until the next open otherwise
+    let policy = CachePolicy {
+        fresh_secs: calendar::quote_ttl(Market::Krx, time::OffsetDateTime::now_utc()),
+        stale_secs: 0,
     };
 
-    Ok(res)
+    // Check cache first; only one caller fetches current price data of all companies in krx
+    cache::get_or_fetch_json(cache_key, policy, fetch_krx_prices_all).await
 }
 
 #[tracing::instrument(err)]
@@ -84,45 +124,169 @@ pub async fn get_price_latest(stock_code: &str) -> Result<krx::ResBody> {
 }
 
 #[tracing::instrument(err)]
-pub async fn get_price_daily(stock_code: &str) -> Result<StockDayPriceRes> {
-    const SQL: &str =
-        "SELECT * from price WHERE srtn_cd=$1::CHAR(6) ORDER BY bas_dt DESC LIMIT 400;";
-
-    let mut rows = db::query(SQL, &[&stock_code]).await?;
-    if rows.is_empty() {
+pub async fn get_price_daily(stock_code: &str, page: &Page) -> Result<StockDayPriceRes> {
+    let sql = page.sql("price", "srtn_cd", "CHAR(6)", "bas_dt");
+    let limit = page.fetch_limit();
+    let params: [&(dyn tokio_postgres::types::ToSql + Sync); 5] =
+        [&stock_code, &page.from, &page.to, &page.cursor, &limit];
+
+    let mut rows = db::query(&sql, &params).await?;
+    if rows.is_empty() && page.cursor.is_none() {
         update_price_db(stock_code).await?;
-        rows = db::query(SQL, &[&stock_code]).await?;
+        rows = db::query(&sql, &params).await?;
     }
 
-    let res = rows
-        .into_iter()
-        .map(|row| StockDayPrice::from(&row))
-        .collect();
+    // Stored data may be behind the market
+    let stale = match jobs::refresh_if_behind(Market::Krx, stock_code).await? {
+        Freshness::Refreshed => {
+            rows = db::query(&sql, &params).await?;
+            false
+        }
+        freshness => freshness == Freshness::Stale,
+    };
+
+    let mut res: Vec<StockDayPrice> = rows.iter().map(StockDayPrice::from).collect();
+    let next_cursor = page.split(&mut res, |v| v.date);
 
     Ok(StockDayPriceRes {
         srtn_cd: stock_code.to_string(),
         prices: res,
+        stale,
+        next_cursor,
     })
 }
 
 #[tracing::instrument(err)]
-pub async fn get_price_weekly(stock_code: &str) -> Result<StockWeeklyPriceRes> {
-    const SQL: &str = "SELECT * from price_weekly WHERE srtn_cd=$1::CHAR(6) ORDER BY opening_date DESC LIMIT 400;";
+pub async fn get_price_weekly(stock_code: &str, page: &Page) -> Result<StockWeeklyPriceRes> {
+    let sql = page.sql("price_weekly", "srtn_cd", "CHAR(6)", "opening_date");
+    let limit = page.fetch_limit();
+    let params: [&(dyn tokio_postgres::types::ToSql + Sync); 5] =
+        [&stock_code, &page.from, &page.to, &page.cursor, &limit];
+
+    let mut rows = db::query(&sql, &params).await?;
+    if rows.is_empty() && page.cursor.is_none() {
+        update_price_db(stock_code).await?;
+        rows = db::query(&sql, &params).await?;
+    }
+
+    // Stored data may be behind the market
+    let stale = match jobs::refresh_if_behind(Market::Krx, stock_code).await? {
+        Freshness::Refreshed => {
+            rows = db::query(&sql, &params).await?;
+            false
+        }
+        freshness => freshness == Freshness::Stale,
+    };
+
+    let mut res: Vec<StockWeekPrice> = rows.iter().map(StockWeekPrice::from).collect();
+    let next_cursor = page.split(&mut res, |v| v.opening_date);
+
+    Ok(StockWeeklyPriceRes {
+        srtn_cd: stock_code.to_string(),
+        prices: res,
+        stale,
+        next_cursor,
+    })
+}
 
-    let mut rows = db::query(SQL, &[&stock_code]).await?;
+#[tracing::instrument(err)]
+pub async fn get_price_period(
+    stock_code: &str,
+    period: Period,
+    page: &Page,
+) -> Result<StockPeriodPriceRes> {
+    let table = format!("price_{}", period.as_str());
+    let sql = page.sql(&table, "srtn_cd", "CHAR(6)", "opening_date");
+    let limit = page.fetch_limit();
+    let params: [&(dyn tokio_postgres::types::ToSql + Sync); 5] =
+        [&stock_code, &page.from, &page.to, &page.cursor, &limit];
+
+    let mut rows = db::query(&sql, &params).await?;
+    if rows.is_empty() && page.cursor.is_none() {
+        update_price_db(stock_code).await?;
+        update_period_price_from_db(stock_code, None, &Period::LONG).await?;
+        rows = db::query(&sql, &params).await?;
+    }
+
+    // Stored data may be behind the market
+    let stale = match jobs::refresh_if_behind(Market::Krx, stock_code).await? {
+        Freshness::Refreshed => {
+            rows = db::query(&sql, &params).await?;
+            false
+        }
+        freshness => freshness == Freshness::Stale,
+    };
+
+    let mut res: Vec<StockPeriodPrice> = rows.iter().map(StockPeriodPrice::from).collect();
+    let next_cursor = page.split(&mut res, |v| v.opening_date);
+
+    Ok(StockPeriodPriceRes {
+        srtn_cd: stock_code.to_string(),
+        period: period.as_str().to_string(),
+        prices: res,
+        stale,
+        next_cursor,
+    })
+}
+
+#[tracing::instrument(err)]
+pub async fn get_price_bars(stock_code: &str, bars: &Bars) -> Result<StockBarsRes> {
+    const SQL: &str = "
+        SELECT * FROM price WHERE srtn_cd=$1::CHAR(6)
+            AND ($2::DATE IS NULL OR bas_dt >= $2::DATE)
+            AND ($3::DATE IS NULL OR bas_dt <= $3::DATE)
+        ORDER BY bas_dt;";
+
+    let from = bars.query_from()?;
+    let mut rows = db::query(SQL, &[&stock_code, &from, &bars.to]).await?;
     if rows.is_empty() {
         update_price_db(stock_code).await?;
-        rows = db::query(SQL, &[&stock_code]).await?;
+        rows = db::query(SQL, &[&stock_code, &from, &bars.to]).await?;
     }
 
-    let res = rows
+    // Stored data may be behind the market
+    let stale = match jobs::refresh_if_behind(Market::Krx, stock_code).await? {
+        Freshness::Refreshed => {
+            rows = db::query(SQL, &[&stock_code, &from, &bars.to]).await?;
+            false
+        }
+        freshness => freshness == Freshness::Stale,
+    };
+
+    // exchange did not happen for the company on those days for a reason
+    let prices: Vec<StockPriceItem> = rows
+        .iter()
+        .map(StockPriceItem::from)
+        .filter(|item| item.mkp != 0)
+        .collect();
+
+    let res = bars
+        .group(&prices, |v| v.bas_dt)?
         .into_iter()
-        .map(|row| StockWeekPrice::from(&row))
+        .map(|bucket| {
+            let v = bucket.items;
+            let (open, close, high, low, volume, trading_value, base) = aggregate_prices(&v);
+            StockBar {
+                start: bucket.start,
+                opening_date: v[0].bas_dt,
+                closing_date: v[v.len() - 1].bas_dt,
+                open,
+                close,
+                high,
+                low,
+                volume,
+                trading_value,
+                base_stock_cnt: base,
+            }
+        })
         .collect();
 
-    Ok(StockWeeklyPriceRes {
+    Ok(StockBarsRes {
         srtn_cd: stock_code.to_string(),
-        prices: res,
+        interval: bars.interval.to_string(),
+        anchor: bars.anchor,
+        bars: res,
+        stale,
     })
 }
 
@@ -138,9 +302,18 @@ pub async fn get_price_exists(stock_code: &str) -> Result<StockPriceExistsRes> {
     })
 }
 
+#[tracing::instrument(err)]
+pub async fn get_stored_codes() -> Result<Vec<String>> {
+    const SQL: &str = "SELECT DISTINCT srtn_cd FROM price ORDER BY srtn_cd;";
+
+    let rows = db::query(SQL, &[]).await?;
+
+    Ok(rows.into_iter().map(|row| row.get("srtn_cd")).collect())
+}
+
 #[tracing::instrument(err)]
 pub async fn clear_prices() -> Result<()> {
-    const SQL: &str = "TRUNCATE TABLE price, price_weekly RESTART IDENTITY;";
+    const SQL: &str = "TRUNCATE TABLE price, price_weekly, price_monthly, price_quarterly, price_yearly, price_backfill RESTART IDENTITY;";
 
     let db_client = db::pool().get().await?;
     db_client.simple_query(SQL).await?;
@@ -191,40 +364,42 @@ async fn fetch_krx_prices_all() -> Result<krx::ResBody> {
         .error_for_status()?;
 
     // Get latest date of the data from KRX
-    let res = web_client
-        .get(url_date)
-        .header(reqwest::header::REFERER, &referer_price)
-        .query(&[
-            ("baseName", "krx.mdc.i18n.component"),
-            ("key", "B128.bld"),
-            ("locale", "ko"),
-        ])
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<web::krx::LatestDateRes>()
-        .await?;
+    let res = metrics::send(
+        Upstream::Krx,
+        web_client
+            .get(url_date)
+            .header(reqwest::header::REFERER, &referer_price)
+            .query(&[
+                ("baseName", "krx.mdc.i18n.component"),
+                ("key", "B128.bld"),
+                ("locale", "ko"),
+            ]),
+    )
+    .await?
+    .json::<web::krx::LatestDateRes>()
+    .await?;
 
     let date_latest = res.result.output[0].get("max_work_dt").unwrap();
 
     // Fetch data from internet
-    let res = web_client
-        .post(url_price)
-        .header(reqwest::header::REFERER, &referer_price)
-        .form(&[
-            ("bld", "dbms/MDC/STAT/standard/MDCSTAT01501"),
-            ("locale", "ko_KR"),
-            ("mktId", "ALL"),
-            ("share", "1"),
-            ("money", "1"),
-            ("csvxls_isNo", "false"),
-            ("trdDd", date_latest),
-        ])
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<web::krx::ResBody>()
-        .await?;
+    let res = metrics::send(
+        Upstream::Krx,
+        web_client
+            .post(url_price)
+            .header(reqwest::header::REFERER, &referer_price)
+            .form(&[
+                ("bld", "dbms/MDC/STAT/standard/MDCSTAT01501"),
+                ("locale", "ko_KR"),
+                ("mktId", "ALL"),
+                ("share", "1"),
+                ("money", "1"),
+                ("csvxls_isNo", "false"),
+                ("trdDd", date_latest),
+            ]),
+    )
+    .await?
+    .json::<web::krx::ResBody>()
+    .await?;
 
     Ok(krx::ResBody::from(res))
 }
@@ -232,175 +407,452 @@ async fn fetch_krx_prices_all() -> Result<krx::ResBody> {
 #[tracing::instrument(err)]
 async fn update_prices_web(
     stock_code: &str,
-    date_from: Option<time::Date>,
+    date_from: time::Date,
+    date_to: time::Date,
 ) -> Result<Vec<StockPriceItem>> {
+    let prices = fetch_prices_web(stock_code, date_from, date_to).await?;
+    if prices.is_empty() {
+        return Err(Error::E404NotFound("No data found from web".into()));
+    }
+
+    // Store in DB
+    let mut db_client = db::pool().get().await?;
+    let transaction = db_client.transaction().await?;
+    insert_prices(&transaction, &prices).await?;
+    transaction.commit().await?;
+
+    Ok(prices)
+}
+
+/// Update daily prices of every listed company from the market-wide feed
+///
+/// The last few trading days are fetched again in case one of them was published late;
+/// rows already in DB are kept as they are. Earlier history of a company new to DB is left to
+/// `build_price_db`, which does not depend on what is stored
+#[tracing::instrument(err)]
+pub async fn update_market_price_db() -> Result<MarketPriceRes> {
+    let lookback = Settings::instance().prices.market_lookback_days.max(1);
+    let mut day = Market::Krx.last_completed_trading_day(time::OffsetDateTime::now_utc());
+    let mut days = Vec::with_capacity(lookback as usize);
+    for _ in 0..lookback {
+        days.push(day);
+        day = Market::Krx.previous_trading_day(day);
+    }
+    days.reverse();
+
+    let mut res = MarketPriceRes {
+        days: 0,
+        latest: None,
+        inserted: 0,
+        symbols: 0,
+    };
+
+    // The earliest new date of each symbol
+    let mut touched: std::collections::BTreeMap<String, time::Date> =
+        std::collections::BTreeMap::new();
+    let total = days.len();
+    for (i, day) in days.into_iter().enumerate() {
+        jobs::report_progress(i, total).await;
+        let prices = fetch_market_prices_web(day).await?;
+        if prices.is_empty() {
+            // not published yet
+            continue;
+        }
+        res.days += 1;
+        res.latest = Some(day);
+
+        let mut db_client = db::pool().get().await?;
+        let transaction = db_client.transaction().await?;
+        let codes = insert_prices(&transaction, &prices).await?;
+        transaction.commit().await?;
+
+        res.inserted += codes.len() as u64;
+        for code in codes {
+            touched.entry(code).or_insert(day);
+        }
+    }
+
+    for (code, since) in &touched {
+        update_period_price_from_db(code, Some(*since), &Period::ALL).await?;
+    }
+    res.symbols = touched.len();
+
+    Ok(res)
+}
+
+/// Daily prices of every listed company on `date`; empty until the day is published
+async fn fetch_market_prices_web(date: time::Date) -> Result<Vec<StockPriceItem>> {
+    let date_format = time::macros::format_description!("[year][month][day]");
+    let bas_dt = date.format(&date_format)?;
+
+    fetch_pages_web(&[("basDt", bas_dt.as_str())]).await
+}
+
+/// Daily prices of a company from `date_from` to `date_to`, both inclusive
+async fn fetch_prices_web(
+    stock_code: &str,
+    date_from: time::Date,
+    date_to: time::Date,
+) -> Result<Vec<StockPriceItem>> {
+    let date_format = time::macros::format_description!("[year][month][day]");
+    let begin = date_from.format(&date_format)?;
+    // endBasDt is exclusive
+    let end = date_to.next_day().unwrap().format(&date_format)?;
+
+    fetch_pages_web(&[
+        ("likeSrtnCd", stock_code),
+        ("beginBasDt", begin.as_str()),
+        ("endBasDt", end.as_str()),
+    ])
+    .await
+}
+
+/// Request every page of the results matching `params`
+#[tracing::instrument(err)]
+async fn fetch_pages_web(params: &[(&str, &str)]) -> Result<Vec<StockPriceItem>> {
+    const PAGE_SIZE: u32 = 1000;
+
     let web_client = reqwest::Client::new();
     let key = Settings::instance().keys.data_go_kr.clone();
     let url = Settings::instance().urls.kr_price.clone();
     let req_url = reqwest::Url::parse(&url).unwrap();
     let host = req_url.host_str().unwrap();
+    let page_size = PAGE_SIZE.to_string();
 
-    // Get all prices
-    let req_url_with_params = if let Some(from) = date_from {
-        let date_format = time::macros::format_description!("[year][month][day]");
-        reqwest::Url::parse_with_params(
+    let mut prices: Vec<StockPriceItem> = Vec::new();
+    for page_no in 1u32.. {
+        let page_no = page_no.to_string();
+        let req_url_with_params = reqwest::Url::parse_with_params(
             &url,
-            &[
+            [
                 ("serviceKey", key.as_str()),
                 ("resultType", "json"),
-                ("numOfRows", "1000000"),
-                ("likeSrtnCd", stock_code),
-                ("beginBasDt", from.format(&date_format)?.as_str()),
-            ],
+                ("numOfRows", page_size.as_str()),
+                ("pageNo", page_no.as_str()),
+            ]
+            .iter()
+            .chain(params),
         )
-        .unwrap()
-    } else {
-        reqwest::Url::parse_with_params(
-            &url,
-            &[
-                ("serviceKey", key.as_str()),
-                ("resultType", "json"),
-                ("numOfRows", "1000000"),
-                ("likeSrtnCd", stock_code),
-            ],
+        .unwrap();
+
+        let res = metrics::send(
+            Upstream::DataGoKr,
+            web_client
+                .get(req_url_with_params)
+                .header(reqwest::header::HOST, host)
+                .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8"),
         )
-        .unwrap()
-    };
-
-    let res = web_client
-        .get(req_url_with_params)
-        .header(reqwest::header::HOST, host)
-        .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8")
-        .send()
         .await?
-        .error_for_status()?
         .json::<StockPrice>()
         .await?;
 
-    if res.response.body.total_count < 1 {
-        return Err(Error::E404NotFound("No data found from web".into()));
+        let body = res.response.body;
+        if body.total_count < 1 || body.items.item.is_empty() {
+            break;
+        }
+        prices.extend(body.items.item);
+        if prices.len() >= body.total_count as usize {
+            break;
+        }
     }
 
-    let prices = res.response.body.items.item;
+    Ok(prices)
+}
+
+/// Copy daily prices into a staging table and merge them, skipping the ones already in DB
+///
+/// Returns the codes of the inserted rows
+async fn insert_prices(
+    transaction: &tokio_postgres::Transaction<'_>,
+    prices: &[StockPriceItem],
+) -> Result<Vec<String>> {
+    const COLUMNS: [&str; 15] = [
+        "bas_dt",
+        "srtn_cd",
+        "isin_cd",
+        "itms_nm",
+        "mrkt_ctg",
+        "clpr",
+        "vs",
+        "flt_rt",
+        "mkp",
+        "hipr",
+        "lopr",
+        "trqu",
+        "tr_prc",
+        "lstg_st_cnt",
+        "mrkt_tot_amt",
+    ];
+
+    let rows: Vec<_> = prices
+        .iter()
+        .map(|item| -> db::CopyRow {
+            vec![
+                &item.bas_dt,
+                &item.srtn_cd,
+                &item.isin_cd,
+                &item.itms_nm,
+                &item.mrkt_ctg,
+                &item.clpr,
+                &item.vs,
+                &item.flt_rt,
+                &item.mkp,
+                &item.hipr,
+                &item.lopr,
+                &item.trqu,
+                &item.tr_prc,
+                &item.lstg_st_cnt,
+                &item.mrkt_tot_amt,
+            ]
+        })
+        .collect();
+    let staging = db::copy_in(transaction, "price", &COLUMNS, &rows).await?;
+
+    let sql_merge = format!(
+        "INSERT INTO price({columns}) SELECT {columns} FROM {staging}
+        ON CONFLICT (bas_dt,srtn_cd) DO NOTHING
+        RETURNING srtn_cd;",
+        columns = COLUMNS.join(","),
+    );
+    let rows = transaction.query(&sql_merge, &[]).await?;
 
-    // Store in DB
-    const SQL_INSERT: &str = "
-        INSERT INTO price(bas_dt,srtn_cd,isin_cd,itms_nm,mrkt_ctg,
-            clpr,vs,flt_rt,mkp,hipr,lopr,trqu,tr_prc,lstg_st_cnt,mrkt_tot_amt)
-        VALUES ($1::DATE,
-                $2::CHAR(6),
-                $3::CHAR(12),
-                $4::VARCHAR(120),
-                $5::VARCHAR(6),
-                $6::INTEGER,
-                $7::INTEGER,
-                $8::REAL,
-                $9::INTEGER,
-                $10::INTEGER,
-                $11::INTEGER,
-                $12::DECIMAL,
-                $13::DECIMAL,
-                $14::DECIMAL,
-                $15::DECIMAL)
-        ON CONFLICT (bas_dt,srtn_cd) DO NOTHING;";
+    Ok(rows.iter().map(|row| row.get("srtn_cd")).collect())
+}
 
+#[tracing::instrument(skip(map), err)]
+async fn update_period_price_db(map: PeriodPriceHashMap<'_>, period: Period) -> Result<()> {
     let mut db_client = db::pool().get().await?;
     let transaction = db_client.transaction().await?;
-    let sql_insert = transaction.prepare(SQL_INSERT).await?;
-
-    for item in &prices {
-        transaction
-            .query(
-                &sql_insert,
-                &[
-                    &item.bas_dt,
-                    &item.srtn_cd,
-                    &item.isin_cd,
-                    &item.itms_nm,
-                    &item.mrkt_ctg,
-                    &item.clpr,
-                    &item.vs,
-                    &item.flt_rt,
-                    &item.mkp,
-                    &item.hipr,
-                    &item.lopr,
-                    &item.trqu,
-                    &item.tr_prc,
-                    &item.lstg_st_cnt,
-                    &item.mrkt_tot_amt,
-                ],
-            )
-            .await?;
-    }
-    transaction.commit().await?;
+    upsert_period_price(&transaction, map, period).await?;
 
-    Ok(prices)
+    Ok(transaction.commit().await?)
 }
 
+/// Rebuild every derived table of a company purely from its stored daily prices
+///
+/// Rows whose values are unchanged are left alone; rows of periods that no longer exist are deleted
 #[tracing::instrument(err)]
-async fn update_weekly_price_db(map: WeeklyPriceHashMap) -> Result<()> {
-    // Store in DB
-    const SQL_INSERT_WEEKLY: &str = "
-        INSERT INTO price_weekly(srtn_cd,year,week,opening_date,closing_date,
-            open,close,high,low,volume,trading_value,base_stock_cnt) 
-        VALUES ($1::CHAR(6),
-                $2::INTEGER,
-                $3::INTEGER,
-                $4::DATE,
-                $5::DATE,
-                $6::DECIMAL,
-                $7::DECIMAL,
-                $8::DECIMAL,
-                $9::DECIMAL,
-                $10::DECIMAL,
-                $11::DECIMAL,
-                $12::DECIMAL)
-        ON CONFLICT (srtn_cd,year,week) DO UPDATE SET
-            closing_date = EXCLUDED.closing_date,
-            open = EXCLUDED.open,
-            close = EXCLUDED.close,
-            high = EXCLUDED.high,
-            low = EXCLUDED.low,
-            volume = EXCLUDED.volume,
-            base_stock_cnt = EXCLUDED.base_stock_cnt;";
+pub async fn rebuild_derived_price_db(stock_code: &str) -> Result<RebuildSymbolRes> {
+    let mut db_client = db::pool().get().await?;
+    let transaction = db_client.transaction().await?;
+    let res = rebuild_derived(&transaction, stock_code).await?;
+
+    transaction.commit().await?;
+    Ok(res)
+}
+
+/// Delete daily prices of a company, optionally within a date range, and rebuild what is derived from them
+#[tracing::instrument(err)]
+pub async fn delete_prices(
+    stock_code: &str,
+    from: Option<time::Date>,
+    to: Option<time::Date>,
+) -> Result<PriceDeleteRes> {
+    const SQL_DELETE: &str = "
+        DELETE FROM price WHERE srtn_cd=$1::CHAR(6)
+            AND ($2::DATE IS NULL OR bas_dt >= $2::DATE)
+            AND ($3::DATE IS NULL OR bas_dt <= $3::DATE);";
+    // Deleted prices are downloaded again by the next build
+    const SQL_DELETE_CHECKPOINT: &str = "DELETE FROM price_backfill WHERE srtn_cd=$1::CHAR(6);";
 
     let mut db_client = db::pool().get().await?;
     let transaction = db_client.transaction().await?;
-    let sql_insert_weekly = transaction.prepare(SQL_INSERT_WEEKLY).await?;
-
-    for (k, mut v) in map {
-        let (year, week) = k;
-        v.sort_by_key(|a| a.bas_dt);
-
-        let (open, close, high, low, volume, trading_value, base) = aggregate_to_weekly(&v);
-
-        transaction
-            .query(
-                &sql_insert_weekly,
-                &[
-                    &v[0].srtn_cd,
-                    &year,
-                    &i32::from(week),
-                    &v[0].bas_dt,
-                    &v[v.len() - 1].bas_dt,
-                    &open,
-                    &close,
-                    &high,
-                    &low,
-                    &volume,
-                    &trading_value,
-                    &base,
-                ],
+
+    let deleted = transaction
+        .execute(SQL_DELETE, &[&stock_code, &from, &to])
+        .await?;
+    transaction
+        .execute(SQL_DELETE_CHECKPOINT, &[&stock_code])
+        .await?;
+    let derived = rebuild_derived(&transaction, stock_code).await?;
+
+    transaction.commit().await?;
+    Ok(PriceDeleteRes {
+        symbol: stock_code.to_string(),
+        deleted,
+        derived_changed: derived.changed,
+        derived_deleted: derived.deleted,
+    })
+}
+
+/// See `rebuild_derived_price_db`; runs in the given transaction
+async fn rebuild_derived(
+    transaction: &deadpool_postgres::Transaction<'_>,
+    stock_code: &str,
+) -> Result<RebuildSymbolRes> {
+    const SQL_SELECT: &str = "SELECT * FROM price WHERE srtn_cd=$1::CHAR(6) ORDER BY bas_dt;";
+
+    let rows = transaction.query(SQL_SELECT, &[&stock_code]).await?;
+    let prices: Vec<StockPriceItem> = rows.iter().map(StockPriceItem::from).collect();
+
+    let mut res = RebuildSymbolRes {
+        symbol: stock_code.to_string(),
+        changed: 0,
+        deleted: 0,
+    };
+    for period in Period::ALL {
+        let map = map_by_period(&prices, period);
+
+        // Periods to keep, keyed the way they are about to be written
+        let mut years: Vec<i32> = Vec::with_capacity(map.len());
+        let mut numbers: Vec<i32> = Vec::with_capacity(map.len());
+        let mut opening_dates: Vec<time::Date> = Vec::with_capacity(map.len());
+        for (&(year, number), v) in &map {
+            years.push(year);
+            numbers.push(i32::from(number));
+            opening_dates.push(v.iter().map(|a| a.bas_dt).min().unwrap_or(time::Date::MIN));
+        }
+
+        let number = match period.column() {
+            Some(column) => format!("AND k.number = t.{}", column),
+            None => String::new(),
+        };
+        let sql_delete = format!(
+            "
+            DELETE FROM price_{table} AS t WHERE srtn_cd=$1::CHAR(6) AND NOT EXISTS (
+                SELECT 1 FROM UNNEST($2::INTEGER[], $3::INTEGER[], $4::DATE[])
+                    AS k(year, number, opening_date)
+                WHERE k.year = t.year AND k.opening_date = t.opening_date {number});",
+            table = period.as_str(),
+        );
+
+        res.deleted += transaction
+            .execute(
+                &sql_delete,
+                &[&stock_code, &years, &numbers, &opening_dates],
             )
             .await?;
+        res.changed += upsert_period_price(transaction, map, period).await?;
     }
 
-    Ok(transaction.commit().await?)
+    Ok(res)
+}
+
+/// Rebuild derived tables of the given companies, or of every stored one
+#[tracing::instrument(err)]
+pub async fn rebuild_derived_price_all(symbols: Option<Vec<String>>) -> Result<RebuildRes> {
+    let symbols = match symbols {
+        Some(symbols) => symbols,
+        None => get_stored_codes().await?,
+    };
+
+    let mut res = RebuildRes {
+        count: symbols.len(),
+        changed: 0,
+        deleted: 0,
+        symbols: Vec::new(),
+    };
+    for (i, symbol) in symbols.into_iter().enumerate() {
+        jobs::report_progress(i, res.count).await;
+        let r = rebuild_derived_price_db(&symbol).await?;
+        res.changed += r.changed;
+        res.deleted += r.deleted;
+        if r.changed > 0 || r.deleted > 0 {
+            res.symbols.push(r);
+        }
+    }
+
+    Ok(res)
+}
+
+/// Insert or update aggregated prices; returns the number of rows that changed
+async fn upsert_period_price(
+    transaction: &deadpool_postgres::Transaction<'_>,
+    map: PeriodPriceHashMap<'_>,
+    period: Period,
+) -> Result<u64> {
+    const VALUES: [&str; 9] = [
+        "opening_date",
+        "closing_date",
+        "open",
+        "close",
+        "high",
+        "low",
+        "volume",
+        "trading_value",
+        "base_stock_cnt",
+    ];
+
+    // A year has no number within it
+    let mut key = vec!["srtn_cd", "year"];
+    key.extend(period.column());
+    let columns = [key.as_slice(), &VALUES].concat();
+
+    let aggregated: Vec<_> = map
+        .into_iter()
+        .map(|((year, number), mut v)| {
+            v.sort_by_key(|a| a.bas_dt);
+            let (open, close, high, low, volume, trading_value, base) = aggregate_prices(&v);
+            let values = [open, close, high, low, volume, trading_value, base];
+            let dates = (v[0].bas_dt, v[v.len() - 1].bas_dt);
+            (
+                v[0].srtn_cd.as_str(),
+                year,
+                i32::from(number),
+                dates,
+                values,
+            )
+        })
+        .collect();
+
+    let mut rows: Vec<db::CopyRow> = Vec::with_capacity(aggregated.len());
+    for (code, year, number, (opening, closing), values) in &aggregated {
+        let mut row: db::CopyRow = vec![code, year];
+        if period.column().is_some() {
+            row.push(number);
+        }
+        row.push(opening);
+        row.push(closing);
+        for value in values {
+            row.push(value);
+        }
+        rows.push(row);
+    }
+
+    let table = format!("price_{}", period.as_str());
+    let staging = db::copy_in(transaction, &table, &columns, &rows).await?;
+
+    let sql_merge = db::upsert_sql(&table, &staging, &key, &VALUES);
+    Ok(transaction.execute(&sql_merge, &[]).await?)
+}
+
+/// Rebuild prices of `periods` from stored daily prices
+///
+/// Starts from the beginning of the periods containing `since` so that every touched period is complete
+#[tracing::instrument(err)]
+async fn update_period_price_from_db(
+    stock_code: &str,
+    since: Option<time::Date>,
+    periods: &[Period],
+) -> Result<()> {
+    const SQL: &str = "
+        SELECT * FROM price WHERE srtn_cd=$1::CHAR(6)
+            AND ($2::DATE IS NULL OR bas_dt >= $2::DATE)
+        ORDER BY bas_dt;";
+
+    // A week may start in the previous year
+    let since = match since {
+        Some(date) => periods
+            .iter()
+            .map(|period| period.start(&date))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .min(),
+        None => None,
+    };
+    let rows = db::query(SQL, &[&stock_code, &since]).await?;
+    let prices: Vec<StockPriceItem> = rows.iter().map(StockPriceItem::from).collect();
+
+    for &period in periods {
+        let map = map_by_period(&prices, period);
+        update_period_price_db(map, period).await?;
+    }
+    Ok(())
 }
 
 #[tracing::instrument(skip_all)]
-fn map_by_week(prices: Vec<StockPriceItem>) -> WeeklyPriceHashMap {
-    let mut map: WeeklyPriceHashMap = std::collections::HashMap::with_capacity(prices.len());
+fn map_by_period(prices: &[StockPriceItem], period: Period) -> PeriodPriceHashMap<'_> {
+    let mut map: PeriodPriceHashMap = std::collections::HashMap::with_capacity(prices.len());
 
     for item in prices {
         if item.mkp == 0 {
@@ -408,7 +860,7 @@ fn map_by_week(prices: Vec<StockPriceItem>) -> WeeklyPriceHashMap {
             continue;
         }
 
-        let k = (item.bas_dt.year(), item.bas_dt.sunday_based_week());
+        let k = period.key(&item.bas_dt);
         if let Some(v) = map.get_mut(&k) {
             v.push(item);
         } else {
@@ -418,8 +870,8 @@ fn map_by_week(prices: Vec<StockPriceItem>) -> WeeklyPriceHashMap {
     map
 }
 
-fn aggregate_to_weekly(
-    v: &Vec<StockPriceItem>,
+fn aggregate_prices(
+    v: &[&StockPriceItem],
 ) -> (
     Decimal,
     Decimal,
@@ -1,23 +1,65 @@
 use rust_decimal::prelude::*;
 
 use crate::model::{
-    StockPriceUS, StockUSDayPriceRes, StockUSPriceExistsRes, StockUSWeekPrice,
-    StockUSWeeklyPriceRes, stockprice_us_from_yahoo, web,
+    BackfillRes, PriceDeleteRes, RebuildRes, RebuildSymbolRes, StockPriceUS, StockUSBar,
+    StockUSBarsRes, StockUSDayPriceRes, StockUSPeriodPrice, StockUSPeriodPriceRes,
+    StockUSPriceExistsRes, StockUSWeekPrice, StockUSWeeklyPriceRes, stockprice_us_from_yahoo, web,
+};
+use crate::services::jobs::{self, Freshness};
+use crate::utils::{
+    Result, cache,
+    calendar::{self, Market},
+    datetime::{Period, date_chunks},
+    db,
+    error::Error,
+    metrics::{self, Upstream},
+    page::Page,
+    resample::Bars,
+    settings::{CachePolicy, Settings},
 };
-use crate::utils::{Result, datetime::get_sunday_of_week, db, settings::Settings};
 
-type WeeklyPriceHashMap = std::collections::HashMap<(i32, u8), Vec<StockPriceUS>>;
+type PeriodPriceHashMap<'a> = std::collections::HashMap<(i32, u8), Vec<&'a StockPriceUS>>;
 
+/// Download daily prices since `Settings.backfill.us_start` in chunks and build what is derived from them
+///
+/// Each chunk is stored along with a checkpoint, so an interrupted build resumes where it stopped
 #[tracing::instrument(err)]
-pub async fn build_price_db(ticker: &str) -> Result<()> {
-    // Get prices data from web
-    let res = update_prices_web(ticker, None).await?;
+pub async fn build_price_db(ticker: &str) -> Result<BackfillRes> {
+    let backfill = jobs::Backfill::load(Market::Us, ticker).await?;
+    let mut res = BackfillRes {
+        symbol: ticker.to_string(),
+        from: backfill.resumed.unwrap_or(backfill.start),
+        to: backfill.end,
+        chunks: 0,
+        fetched: 0,
+        inserted: 0,
+    };
 
-    // Aggregate prices according to week
-    let map = map_by_week(res);
+    let chunk_days = Settings::instance().backfill.chunk_days;
+    let chunks = date_chunks(res.from, backfill.end, chunk_days);
+    for (i, &(from, to)) in chunks.iter().enumerate() {
+        jobs::report_progress(i, chunks.len()).await;
+        let prices = fetch_prices_web(ticker, from, to).await?;
+
+        let mut db_client = db::pool().get().await?;
+        let transaction = db_client.transaction().await?;
+        let inserted = insert_prices(&transaction, ticker, &prices).await?;
+        backfill.save(&transaction, to.next_day().unwrap()).await?;
+        transaction.commit().await?;
+
+        res.chunks += 1;
+        res.fetched += prices.len();
+        res.inserted += inserted;
+    }
 
-    // Update DB
-    update_weekly_price_db(ticker, map).await
+    if backfill.resumed.is_none() && res.fetched == 0 {
+        backfill.delete().await?;
+        return Err(Error::E404NotFound("No data found from web".into()));
+    }
+
+    // From the start since an earlier run may have stopped before this step
+    update_period_price_from_db(ticker, Some(backfill.start), &Period::ALL).await?;
+    Ok(res)
 }
 
 #[tracing::instrument(err)]
@@ -27,23 +69,48 @@ pub async fn update_price_db(ticker: &str) -> Result<()> {
 
     let rows = db::query(SQL_LATEST_DATE, &[&ticker]).await?;
     let last_date: Option<time::Date> = rows[0].get("max");
-    let date_from = match last_date {
-        None => None,
-        Some(date) => Some(get_sunday_of_week(&date)?),
+
+    // Nothing new until another trading day has closed
+    let last_trading_day = Market::Us.last_completed_trading_day(time::OffsetDateTime::now_utc());
+    if last_date.is_some_and(|date| date >= last_trading_day) {
+        return Ok(());
+    }
+
+    let Some(last_date) = last_date else {
+        return build_price_db(ticker).await.map(|_| ());
     };
+    let date_from = Period::Week.start(&last_date)?;
 
     // Get prices data from web from last week
-    let prices = update_prices_web(ticker, date_from).await?;
+    let prices = update_prices_web(ticker, date_from, last_trading_day).await?;
 
     // Aggregate prices according to week
-    let map = map_by_week(prices);
+    let map = map_by_period(&prices, Period::Week);
 
     // Update DB
-    update_weekly_price_db(ticker, map).await
+    update_period_price_db(ticker, map, Period::Week).await?;
+    update_period_price_from_db(ticker, Some(date_from), &Period::LONG).await
 }
 
 #[tracing::instrument(err)]
 pub async fn get_price_latest(ticker: &str) -> Result<StockUSDayPriceRes> {
+    let cache_key = format!("prices_us/latest/{}", ticker);
+
+    // Cache briefly during the session and until the next open otherwise
+    let policy = CachePolicy {
+        fresh_secs: calendar::quote_ttl(Market::Us, time::OffsetDateTime::now_utc()),
+        stale_secs: 0,
+    };
+
+    let ticker = ticker.to_string();
+    cache::get_or_fetch_json(&cache_key, policy, move || async move {
+        fetch_price_latest(&ticker).await
+    })
+    .await
+}
+
+#[tracing::instrument(err)]
+async fn fetch_price_latest(ticker: &str) -> Result<StockUSDayPriceRes> {
     let web_client = reqwest::Client::new();
     let url = Settings::instance().urls.us_price.clone() + "/" + ticker;
     let req_url = reqwest::Url::parse(&url).unwrap();
@@ -72,64 +139,189 @@ pub async fn get_price_latest(ticker: &str) -> Result<StockUSDayPriceRes> {
     )
     .unwrap();
 
-    let res = web_client
-        .get(req_url_with_params)
-        .header(reqwest::header::HOST, host)
-        .header(reqwest::header::ACCEPT, "application/json")
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<web::yahoo::ResBody>()
-        .await?;
+    let res = metrics::send(
+        Upstream::Yahoo,
+        web_client
+            .get(req_url_with_params)
+            .header(reqwest::header::HOST, host)
+            .header(reqwest::header::ACCEPT, "application/json"),
+    )
+    .await?
+    .json::<web::yahoo::ResBody>()
+    .await?;
 
     let prices: Vec<StockPriceUS> = stockprice_us_from_yahoo(&res)?;
 
     Ok(StockUSDayPriceRes {
         ticker: ticker.to_string(),
         prices,
+        stale: false,
+        next_cursor: None,
     })
 }
 
 #[tracing::instrument(err)]
-pub async fn get_price_daily(ticker: &str) -> Result<StockUSDayPriceRes> {
-    const SQL: &str =
-        "SELECT * from price_us WHERE ticker=$1::CHAR(6) ORDER BY date DESC LIMIT 400;";
-
-    let mut rows = db::query(SQL, &[&ticker]).await?;
-    if rows.is_empty() {
+pub async fn get_price_daily(ticker: &str, page: &Page) -> Result<StockUSDayPriceRes> {
+    let sql = page.sql("price_us", "ticker", "VARCHAR(10)", "date");
+    let limit = page.fetch_limit();
+    let params: [&(dyn tokio_postgres::types::ToSql + Sync); 5] =
+        [&ticker, &page.from, &page.to, &page.cursor, &limit];
+
+    let mut rows = db::query(&sql, &params).await?;
+    if rows.is_empty() && page.cursor.is_none() {
         update_price_db(ticker).await?;
-        rows = db::query(SQL, &[&ticker]).await?;
+        rows = db::query(&sql, &params).await?;
     }
 
-    let res = rows
-        .into_iter()
-        .map(|row| StockPriceUS::from(&row))
-        .collect();
+    // Stored data may be behind the market
+    let stale = match jobs::refresh_if_behind(Market::Us, ticker).await? {
+        Freshness::Refreshed => {
+            rows = db::query(&sql, &params).await?;
+            false
+        }
+        freshness => freshness == Freshness::Stale,
+    };
+
+    let mut res: Vec<StockPriceUS> = rows.iter().map(StockPriceUS::from).collect();
+    let next_cursor = page.split(&mut res, |v| v.date);
 
     Ok(StockUSDayPriceRes {
         ticker: ticker.to_string(),
         prices: res,
+        stale,
+        next_cursor,
+    })
+}
+
+#[tracing::instrument(err)]
+pub async fn get_price_weekly(ticker: &str, page: &Page) -> Result<StockUSWeeklyPriceRes> {
+    let sql = page.sql("price_us_weekly", "ticker", "VARCHAR(10)", "opening_date");
+    let limit = page.fetch_limit();
+    let params: [&(dyn tokio_postgres::types::ToSql + Sync); 5] =
+        [&ticker, &page.from, &page.to, &page.cursor, &limit];
+
+    let mut rows = db::query(&sql, &params).await?;
+    if rows.is_empty() && page.cursor.is_none() {
+        update_price_db(ticker).await?;
+        rows = db::query(&sql, &params).await?;
+    }
+
+    // Stored data may be behind the market
+    let stale = match jobs::refresh_if_behind(Market::Us, ticker).await? {
+        Freshness::Refreshed => {
+            rows = db::query(&sql, &params).await?;
+            false
+        }
+        freshness => freshness == Freshness::Stale,
+    };
+
+    let mut res: Vec<StockUSWeekPrice> = rows.iter().map(StockUSWeekPrice::from).collect();
+    let next_cursor = page.split(&mut res, |v| v.opening_date);
+
+    Ok(StockUSWeeklyPriceRes {
+        ticker: ticker.to_string(),
+        prices: res,
+        stale,
+        next_cursor,
     })
 }
 
 #[tracing::instrument(err)]
-pub async fn get_price_weekly(ticker: &str) -> Result<StockUSWeeklyPriceRes> {
-    const SQL: &str = "SELECT * from price_us_weekly WHERE ticker=$1::VARCHAR(10) ORDER BY opening_date DESC LIMIT 400;";
+pub async fn get_price_period(
+    ticker: &str,
+    period: Period,
+    page: &Page,
+) -> Result<StockUSPeriodPriceRes> {
+    let table = format!("price_us_{}", period.as_str());
+    let sql = page.sql(&table, "ticker", "VARCHAR(10)", "opening_date");
+    let limit = page.fetch_limit();
+    let params: [&(dyn tokio_postgres::types::ToSql + Sync); 5] =
+        [&ticker, &page.from, &page.to, &page.cursor, &limit];
+
+    let mut rows = db::query(&sql, &params).await?;
+    if rows.is_empty() && page.cursor.is_none() {
+        update_price_db(ticker).await?;
+        update_period_price_from_db(ticker, None, &Period::LONG).await?;
+        rows = db::query(&sql, &params).await?;
+    }
+
+    // Stored data may be behind the market
+    let stale = match jobs::refresh_if_behind(Market::Us, ticker).await? {
+        Freshness::Refreshed => {
+            rows = db::query(&sql, &params).await?;
+            false
+        }
+        freshness => freshness == Freshness::Stale,
+    };
+
+    let mut res: Vec<StockUSPeriodPrice> = rows.iter().map(StockUSPeriodPrice::from).collect();
+    let next_cursor = page.split(&mut res, |v| v.opening_date);
 
-    let mut rows = db::query(SQL, &[&ticker]).await?;
+    Ok(StockUSPeriodPriceRes {
+        ticker: ticker.to_string(),
+        period: period.as_str().to_string(),
+        prices: res,
+        stale,
+        next_cursor,
+    })
+}
+
+#[tracing::instrument(err)]
+pub async fn get_price_bars(ticker: &str, bars: &Bars) -> Result<StockUSBarsRes> {
+    const SQL: &str = "
+        SELECT * FROM price_us WHERE ticker=$1::VARCHAR(10)
+            AND ($2::DATE IS NULL OR date >= $2::DATE)
+            AND ($3::DATE IS NULL OR date <= $3::DATE)
+        ORDER BY date;";
+
+    let from = bars.query_from()?;
+    let mut rows = db::query(SQL, &[&ticker, &from, &bars.to]).await?;
     if rows.is_empty() {
         update_price_db(ticker).await?;
-        rows = db::query(SQL, &[&ticker]).await?;
+        rows = db::query(SQL, &[&ticker, &from, &bars.to]).await?;
     }
 
-    let res = rows
+    // Stored data may be behind the market
+    let stale = match jobs::refresh_if_behind(Market::Us, ticker).await? {
+        Freshness::Refreshed => {
+            rows = db::query(SQL, &[&ticker, &from, &bars.to]).await?;
+            false
+        }
+        freshness => freshness == Freshness::Stale,
+    };
+
+    // exchange did not happen for the company on those days for a reason
+    let prices: Vec<StockPriceUS> = rows
+        .iter()
+        .map(StockPriceUS::from)
+        .filter(|item| item.open != Decimal::ZERO)
+        .collect();
+
+    let res = bars
+        .group(&prices, |v| v.date)?
         .into_iter()
-        .map(|row| StockUSWeekPrice::from(&row))
+        .map(|bucket| {
+            let v = bucket.items;
+            let (open, high, low, close, volume) = aggregate_prices(&v);
+            StockUSBar {
+                start: bucket.start,
+                opening_date: v[0].date,
+                closing_date: v[v.len() - 1].date,
+                open,
+                high,
+                low,
+                close,
+                volume,
+            }
+        })
         .collect();
 
-    Ok(StockUSWeeklyPriceRes {
+    Ok(StockUSBarsRes {
         ticker: ticker.to_string(),
-        prices: res,
+        interval: bars.interval.to_string(),
+        anchor: bars.anchor,
+        bars: res,
+        stale,
     })
 }
 
@@ -145,9 +337,18 @@ pub async fn get_price_exists(ticker: &str) -> Result<StockUSPriceExistsRes> {
     })
 }
 
+#[tracing::instrument(err)]
+pub async fn get_stored_tickers() -> Result<Vec<String>> {
+    const SQL: &str = "SELECT DISTINCT ticker FROM price_us ORDER BY ticker;";
+
+    let rows = db::query(SQL, &[]).await?;
+
+    Ok(rows.into_iter().map(|row| row.get("ticker")).collect())
+}
+
 #[tracing::instrument(err)]
 pub async fn clear_prices() -> Result<()> {
-    const SQL: &str = "TRUNCATE TABLE price_us, price_us_weekly RESTART IDENTITY;";
+    const SQL: &str = "TRUNCATE TABLE price_us, price_us_weekly, price_us_monthly, price_us_quarterly, price_us_yearly, price_us_backfill RESTART IDENTITY;";
 
     let db_client = db::pool().get().await?;
     db_client.simple_query(SQL).await?;
@@ -158,36 +359,51 @@ pub async fn clear_prices() -> Result<()> {
 #[tracing::instrument(err)]
 async fn update_prices_web(
     ticker: &str,
-    date_from: Option<time::Date>,
+    date_from: time::Date,
+    date_to: time::Date,
+) -> Result<Vec<StockPriceUS>> {
+    let prices = fetch_prices_web(ticker, date_from, date_to).await?;
+
+    // Store in DB
+    let mut db_client = db::pool().get().await?;
+    let transaction = db_client.transaction().await?;
+    insert_prices(&transaction, ticker, &prices).await?;
+    transaction.commit().await?;
+
+    Ok(prices)
+}
+
+/// Daily prices of a ticker from `date_from` to `date_to`, both inclusive
+#[tracing::instrument(err)]
+async fn fetch_prices_web(
+    ticker: &str,
+    date_from: time::Date,
+    date_to: time::Date,
 ) -> Result<Vec<StockPriceUS>> {
     let web_client = reqwest::Client::new();
     let url = Settings::instance().urls.us_price.clone() + "/" + ticker;
     let req_url = reqwest::Url::parse(&url).unwrap();
     let host = req_url.host_str().unwrap();
-    let start_day = match date_from {
-        Some(date) => time::OffsetDateTime::new_in_offset(
-            date,
-            time::Time::from_hms(9, 30, 0).unwrap(), // 9:30ET is the opening time
-            time::UtcOffset::from_hms(-5, 0, 0).unwrap(), // Use EST timezone; MIDNIGHT in EST comes later than in EDT
-        )
+    let start_day = Market::Us
+        .start_of_day(date_from)
         .unix_timestamp()
-        .to_string(),
-        None => "1577836800".to_string(), // 2020-01-01
-    };
-    let prev_day = time::OffsetDateTime::now_utc()
-        .to_offset(time::macros::offset!(-5)) // Use EST timezone; MIDNIGHT in EST comes later than in EDT
-        .replace_time(time::Time::MIDNIGHT)
-        .saturating_sub(time::Duration::SECOND)
+        .to_string();
+
+    // Up to the close of `date_to`; a session in progress is left out
+    let end_day = Market::Us
+        .session(date_to)
+        .map(|s| s.close)
+        .unwrap_or_else(|| Market::Us.start_of_day(date_to.next_day().unwrap()))
+        .min(time::OffsetDateTime::now_utc())
         .unix_timestamp()
         .to_string();
 
-    // Get all codes
     let req_url_with_params = reqwest::Url::parse_with_params(
         &url,
         &[
             ("symbol", ticker),
             ("period1", &start_day),
-            ("period2", &prev_day),
+            ("period2", &end_day),
             ("interval", "1d"),
             ("useYfid", "true"),
             ("includePrePost", "true"),
@@ -199,115 +415,292 @@ async fn update_prices_web(
     )
     .unwrap();
 
-    let res = web_client
-        .get(req_url_with_params)
-        .header(reqwest::header::HOST, host)
-        .header(reqwest::header::ACCEPT, "application/json")
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<web::yahoo::ResBody>()
-        .await?;
+    let res = metrics::send(
+        Upstream::Yahoo,
+        web_client
+            .get(req_url_with_params)
+            .header(reqwest::header::HOST, host)
+            .header(reqwest::header::ACCEPT, "application/json"),
+    )
+    .await?
+    .json::<web::yahoo::ResBody>()
+    .await?;
 
-    let prices: Vec<StockPriceUS> = stockprice_us_from_yahoo(&res)?;
+    stockprice_us_from_yahoo(&res)
+}
 
-    // Store in DB
-    const SQL_INSERT: &str = "
-        INSERT INTO price_us(ticker,date,open,high,low,close,adj_close,volume)
-        VALUES ($1::VARCHAR(10),
-                $2::DATE,
-                $3::DECIMAL,
-                $4::DECIMAL,
-                $5::DECIMAL,
-                $6::DECIMAL,
-                $7::DECIMAL,
-                $8::DECIMAL)
-        ON CONFLICT (ticker,date) DO NOTHING;";
+/// Copy daily prices into a staging table and merge them, skipping the ones already in DB
+async fn insert_prices(
+    transaction: &tokio_postgres::Transaction<'_>,
+    ticker: &str,
+    prices: &[StockPriceUS],
+) -> Result<u64> {
+    const COLUMNS: [&str; 8] = [
+        "ticker",
+        "date",
+        "open",
+        "high",
+        "low",
+        "close",
+        "adj_close",
+        "volume",
+    ];
+
+    let rows: Vec<_> = prices
+        .iter()
+        .map(|item| -> db::CopyRow {
+            vec![
+                &ticker,
+                &item.date,
+                &item.open,
+                &item.high,
+                &item.low,
+                &item.close,
+                &item.adj_close,
+                &item.volume,
+            ]
+        })
+        .collect();
+    let staging = db::copy_in(transaction, "price_us", &COLUMNS, &rows).await?;
 
+    let sql_merge = format!(
+        "INSERT INTO price_us({columns}) SELECT {columns} FROM {staging}
+        ON CONFLICT (ticker,date) DO NOTHING;",
+        columns = COLUMNS.join(","),
+    );
+
+    Ok(transaction.execute(&sql_merge, &[]).await?)
+}
+
+#[tracing::instrument(skip(map), err)]
+async fn update_period_price_db(
+    ticker: &str,
+    map: PeriodPriceHashMap<'_>,
+    period: Period,
+) -> Result<()> {
     let mut db_client = db::pool().get().await?;
     let transaction = db_client.transaction().await?;
-    let sql_insert = transaction.prepare(SQL_INSERT).await?;
-
-    for item in &prices {
-        transaction
-            .query(
-                &sql_insert,
-                &[
-                    &ticker,
-                    &item.date,
-                    &item.open,
-                    &item.high,
-                    &item.low,
-                    &item.close,
-                    &item.adj_close,
-                    &item.volume,
-                ],
-            )
-            .await?;
-    }
-    transaction.commit().await?;
+    upsert_period_price(&transaction, ticker, map, period).await?;
 
-    Ok(prices)
+    Ok(transaction.commit().await?)
 }
 
+/// Rebuild every derived table of a ticker purely from its stored daily prices
+///
+/// Rows whose values are unchanged are left alone; rows of periods that no longer exist are deleted
 #[tracing::instrument(err)]
-async fn update_weekly_price_db(ticker: &str, map: WeeklyPriceHashMap) -> Result<()> {
-    // Store in DB
-    const SQL_INSERT_WEEKLY: &str = "
-        INSERT INTO price_us_weekly(ticker,year,week,opening_date,closing_date,open,high,low,close,volume) 
-        VALUES ($1::VARCHAR(10),
-                $2::INTEGER,
-                $3::INTEGER,
-                $4::DATE,
-                $5::DATE,
-                $6::DECIMAL,
-                $7::DECIMAL,
-                $8::DECIMAL,
-                $9::DECIMAL,
-                $10::DECIMAL)
-        ON CONFLICT (ticker,year,week) DO UPDATE SET
-            closing_date = EXCLUDED.closing_date,
-            open = EXCLUDED.open,
-            high = EXCLUDED.high,
-            low = EXCLUDED.low,
-            close = EXCLUDED.close,
-            volume = EXCLUDED.volume;";
+pub async fn rebuild_derived_price_db(ticker: &str) -> Result<RebuildSymbolRes> {
+    let mut db_client = db::pool().get().await?;
+    let transaction = db_client.transaction().await?;
+    let res = rebuild_derived(&transaction, ticker).await?;
+
+    transaction.commit().await?;
+    Ok(res)
+}
+
+/// Delete daily prices of a ticker, optionally within a date range, and rebuild what is derived from them
+#[tracing::instrument(err)]
+pub async fn delete_prices(
+    ticker: &str,
+    from: Option<time::Date>,
+    to: Option<time::Date>,
+) -> Result<PriceDeleteRes> {
+    const SQL_DELETE: &str = "
+        DELETE FROM price_us WHERE ticker=$1::VARCHAR(10)
+            AND ($2::DATE IS NULL OR date >= $2::DATE)
+            AND ($3::DATE IS NULL OR date <= $3::DATE);";
+    // Deleted prices are downloaded again by the next build
+    const SQL_DELETE_CHECKPOINT: &str =
+        "DELETE FROM price_us_backfill WHERE ticker=$1::VARCHAR(10);";
 
     let mut db_client = db::pool().get().await?;
     let transaction = db_client.transaction().await?;
-    let sql_insert_weekly = transaction.prepare(SQL_INSERT_WEEKLY).await?;
-
-    for (k, mut v) in map {
-        let (year, week) = k;
-        v.sort_by_key(|a| a.date);
-
-        let (open, high, low, close, volume) = aggregate_to_weekly(&v);
-
-        transaction
-            .query(
-                &sql_insert_weekly,
-                &[
-                    &ticker,
-                    &year,
-                    &i32::from(week),
-                    &v[0].date,
-                    &v[v.len() - 1].date,
-                    &open,
-                    &high,
-                    &low,
-                    &close,
-                    &volume,
-                ],
-            )
+
+    let deleted = transaction
+        .execute(SQL_DELETE, &[&ticker, &from, &to])
+        .await?;
+    transaction
+        .execute(SQL_DELETE_CHECKPOINT, &[&ticker])
+        .await?;
+    let derived = rebuild_derived(&transaction, ticker).await?;
+
+    transaction.commit().await?;
+    Ok(PriceDeleteRes {
+        symbol: ticker.to_string(),
+        deleted,
+        derived_changed: derived.changed,
+        derived_deleted: derived.deleted,
+    })
+}
+
+/// See `rebuild_derived_price_db`; runs in the given transaction
+async fn rebuild_derived(
+    transaction: &deadpool_postgres::Transaction<'_>,
+    ticker: &str,
+) -> Result<RebuildSymbolRes> {
+    const SQL_SELECT: &str = "SELECT * FROM price_us WHERE ticker=$1::VARCHAR(10) ORDER BY date;";
+
+    let rows = transaction.query(SQL_SELECT, &[&ticker]).await?;
+    let prices: Vec<StockPriceUS> = rows.iter().map(StockPriceUS::from).collect();
+
+    let mut res = RebuildSymbolRes {
+        symbol: ticker.to_string(),
+        changed: 0,
+        deleted: 0,
+    };
+    for period in Period::ALL {
+        let map = map_by_period(&prices, period);
+
+        // Periods to keep, keyed the way they are about to be written
+        let mut years: Vec<i32> = Vec::with_capacity(map.len());
+        let mut numbers: Vec<i32> = Vec::with_capacity(map.len());
+        let mut opening_dates: Vec<time::Date> = Vec::with_capacity(map.len());
+        for (&(year, number), v) in &map {
+            years.push(year);
+            numbers.push(i32::from(number));
+            opening_dates.push(v.iter().map(|a| a.date).min().unwrap_or(time::Date::MIN));
+        }
+
+        let number = match period.column() {
+            Some(column) => format!("AND k.number = t.{}", column),
+            None => String::new(),
+        };
+        let sql_delete = format!(
+            "
+            DELETE FROM price_us_{table} AS t WHERE ticker=$1::VARCHAR(10) AND NOT EXISTS (
+                SELECT 1 FROM UNNEST($2::INTEGER[], $3::INTEGER[], $4::DATE[])
+                    AS k(year, number, opening_date)
+                WHERE k.year = t.year AND k.opening_date = t.opening_date {number});",
+            table = period.as_str(),
+        );
+
+        res.deleted += transaction
+            .execute(&sql_delete, &[&ticker, &years, &numbers, &opening_dates])
             .await?;
+        res.changed += upsert_period_price(transaction, ticker, map, period).await?;
     }
 
-    Ok(transaction.commit().await?)
+    Ok(res)
+}
+
+/// Rebuild derived tables of the given tickers, or of every stored one
+#[tracing::instrument(err)]
+pub async fn rebuild_derived_price_all(symbols: Option<Vec<String>>) -> Result<RebuildRes> {
+    let symbols = match symbols {
+        Some(symbols) => symbols,
+        None => get_stored_tickers().await?,
+    };
+
+    let mut res = RebuildRes {
+        count: symbols.len(),
+        changed: 0,
+        deleted: 0,
+        symbols: Vec::new(),
+    };
+    for (i, symbol) in symbols.into_iter().enumerate() {
+        jobs::report_progress(i, res.count).await;
+        let r = rebuild_derived_price_db(&symbol).await?;
+        res.changed += r.changed;
+        res.deleted += r.deleted;
+        if r.changed > 0 || r.deleted > 0 {
+            res.symbols.push(r);
+        }
+    }
+
+    Ok(res)
+}
+
+/// Insert or update aggregated prices; returns the number of rows that changed
+async fn upsert_period_price(
+    transaction: &deadpool_postgres::Transaction<'_>,
+    ticker: &str,
+    map: PeriodPriceHashMap<'_>,
+    period: Period,
+) -> Result<u64> {
+    const VALUES: [&str; 7] = [
+        "opening_date",
+        "closing_date",
+        "open",
+        "high",
+        "low",
+        "close",
+        "volume",
+    ];
+
+    // A year has no number within it
+    let mut key = vec!["ticker", "year"];
+    key.extend(period.column());
+    let columns = [key.as_slice(), &VALUES].concat();
+
+    let aggregated: Vec<_> = map
+        .into_iter()
+        .map(|((year, number), mut v)| {
+            v.sort_by_key(|a| a.date);
+            let (open, high, low, close, volume) = aggregate_prices(&v);
+            let values = [open, high, low, close, volume];
+            let dates = (v[0].date, v[v.len() - 1].date);
+            (year, i32::from(number), dates, values)
+        })
+        .collect();
+
+    let mut rows: Vec<db::CopyRow> = Vec::with_capacity(aggregated.len());
+    for (year, number, (opening, closing), values) in &aggregated {
+        let mut row: db::CopyRow = vec![&ticker, year];
+        if period.column().is_some() {
+            row.push(number);
+        }
+        row.push(opening);
+        row.push(closing);
+        for value in values {
+            row.push(value);
+        }
+        rows.push(row);
+    }
+
+    let table = format!("price_us_{}", period.as_str());
+    let staging = db::copy_in(transaction, &table, &columns, &rows).await?;
+    let sql_merge = db::upsert_sql(&table, &staging, &key, &VALUES);
+
+    Ok(transaction.execute(&sql_merge, &[]).await?)
+}
+
+/// Rebuild prices of `periods` from stored daily prices
+///
+/// Starts from the beginning of the periods containing `since` so that every touched period is complete
+#[tracing::instrument(err)]
+async fn update_period_price_from_db(
+    ticker: &str,
+    since: Option<time::Date>,
+    periods: &[Period],
+) -> Result<()> {
+    const SQL: &str = "
+        SELECT * FROM price_us WHERE ticker=$1::VARCHAR(10)
+            AND ($2::DATE IS NULL OR date >= $2::DATE)
+        ORDER BY date;";
+
+    // A week may start in the previous year
+    let since = match since {
+        Some(date) => periods
+            .iter()
+            .map(|period| period.start(&date))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .min(),
+        None => None,
+    };
+    let rows = db::query(SQL, &[&ticker, &since]).await?;
+    let prices: Vec<StockPriceUS> = rows.iter().map(StockPriceUS::from).collect();
+
+    for &period in periods {
+        let map = map_by_period(&prices, period);
+        update_period_price_db(ticker, map, period).await?;
+    }
+    Ok(())
 }
 
 #[tracing::instrument(skip_all)]
-fn map_by_week(prices: Vec<StockPriceUS>) -> WeeklyPriceHashMap {
-    let mut map: WeeklyPriceHashMap = std::collections::HashMap::with_capacity(prices.len());
+fn map_by_period(prices: &[StockPriceUS], period: Period) -> PeriodPriceHashMap<'_> {
+    let mut map: PeriodPriceHashMap = std::collections::HashMap::with_capacity(prices.len());
 
     for item in prices {
         if item.open == Decimal::ZERO {
@@ -315,7 +708,7 @@ fn map_by_week(prices: Vec<StockPriceUS>) -> WeeklyPriceHashMap {
             continue;
         }
 
-        let k = (item.date.year(), item.date.sunday_based_week());
+        let k = period.key(&item.date);
         if let Some(v) = map.get_mut(&k) {
             v.push(item);
         } else {
@@ -326,7 +719,7 @@ fn map_by_week(prices: Vec<StockPriceUS>) -> WeeklyPriceHashMap {
 }
 
 #[tracing::instrument(skip_all)]
-fn aggregate_to_weekly(v: &Vec<StockPriceUS>) -> (Decimal, Decimal, Decimal, Decimal, Decimal) {
+fn aggregate_prices(v: &[&StockPriceUS]) -> (Decimal, Decimal, Decimal, Decimal, Decimal) {
     let last_index = v.len() - 1;
     let open = (v[0].open * v[0].adj_close / v[0].close).round_dp(6);
     let close = v[last_index].adj_close;
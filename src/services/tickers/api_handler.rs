@@ -1,16 +1,69 @@
 use super::provider;
-use crate::utils::Result;
+use crate::services::jobs;
+use crate::utils::{Result, datetime::date_deserialize, error::Error};
 
-#[tracing::instrument(err)]
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
 #[actix_web::post("/tickers")]
 pub async fn handler_post(req: actix_web::HttpRequest) -> Result<actix_web::HttpResponse> {
-    provider::build_ticker_db().await?;
+    let job = jobs::enqueue(jobs::JobKind::BuildTickers).await?;
 
     // Return result
-    Ok(actix_web::HttpResponse::Created().finish())
+    Ok(jobs::accepted(&job))
 }
 
-#[tracing::instrument]
+/// Put back the tickers replaced by the last build
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::post("/tickers/rollback")]
+pub async fn handler_post_rollback(req: actix_web::HttpRequest) -> Result<actix_web::HttpResponse> {
+    provider::rollback_ticker_db().await?;
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().finish())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ParamsListings {
+    #[serde(deserialize_with = "date_deserialize")]
+    from: time::Date,
+    #[serde(deserialize_with = "date_deserialize")]
+    to: time::Date,
+}
+
+/// Tickers listed or delisted between two dates
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::get("/tickers/listings")]
+pub async fn handler_get_listings(
+    req: actix_web::HttpRequest,
+    params: actix_web::web::Query<ParamsListings>,
+) -> Result<actix_web::HttpResponse> {
+    if params.from > params.to {
+        return Err(Error::E400BadRequest("from is after to".into()));
+    }
+
+    let res = provider::get_ticker_listings(params.from, params.to).await?;
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().json(res))
+}
+
+/// Every listing of a ticker, oldest first
+#[tracing::instrument(skip(req), fields(path = %req.path()), err)]
+#[actix_web::get("/tickers/{ticker}/history")]
+pub async fn handler_get_history(
+    req: actix_web::HttpRequest,
+    ticker: actix_web::web::Path<String>,
+) -> Result<actix_web::HttpResponse> {
+    if ticker.is_empty() {
+        return Err(Error::E400BadRequest("invalid ticker".into()));
+    }
+
+    let res = provider::get_ticker_history(&ticker).await?;
+
+    // Return result
+    Ok(actix_web::HttpResponse::Ok().json(res))
+}
+
+#[tracing::instrument(skip(req), fields(path = %req.path()))]
 #[actix_web::get("/tickers/{search_word}")]
 pub async fn handler_get(
     req: actix_web::HttpRequest,
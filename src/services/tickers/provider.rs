@@ -1,51 +1,65 @@
-use crate::model::Ticker;
-use crate::utils::{db, error::Error, settings::Settings, Result};
+use crate::model::{ListingRebuildRes, ListingsRes, Ticker, TickerVersionRes};
+use crate::utils::{
+    Result, db,
+    error::Error,
+    metrics::{self, Upstream},
+    settings::Settings,
+};
 
 #[tracing::instrument(err)]
-pub async fn build_ticker_db() -> Result<()> {
+pub async fn build_ticker_db() -> Result<ListingRebuildRes> {
     let agent = Settings::instance().agent.sec_gov.clone();
     let url = Settings::instance().urls.us_ticker.clone();
     let req_url = reqwest::Url::parse(&url).unwrap();
     let host = req_url.host_str().unwrap();
 
     // Get all codes
-    let res = reqwest::Client::new()
-        .get(req_url.clone())
-        .header(reqwest::header::HOST, host)
-        .header(reqwest::header::USER_AGENT, &agent)
-        .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8")
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<std::collections::HashMap<String, Ticker>>()
-        .await?;
-
-    // Store in DB
-    const SQL_CLEAR: &str = "TRUNCATE TABLE ticker RESTART IDENTITY";
-    const SQL_INSERT: &str =
-        "INSERT INTO ticker(cik_str, ticker, title) VALUES ($1::CHAR(10), $2::VARCHAR(10), $3::TEXT);";
-
-    let mut db_client = db::pool().get().await?;
-    let transaction = db_client.transaction().await?;
-    let sql_insert = transaction.prepare(SQL_INSERT).await?;
-
-    transaction.simple_query(SQL_CLEAR).await?;
-
-    for ticker in res.values() {
-        transaction
-            .query(
-                &sql_insert,
-                &[&ticker.cik_str, &ticker.ticker, &ticker.title],
-            )
-            .await?;
-    }
+    let res = metrics::send(
+        Upstream::Sec,
+        reqwest::Client::new()
+            .get(req_url.clone())
+            .header(reqwest::header::HOST, host)
+            .header(reqwest::header::USER_AGENT, &agent)
+            .header(reqwest::header::ACCEPT, "application/json;charset=UTF-8"),
+    )
+    .await?
+    .json::<std::collections::HashMap<String, Ticker>>()
+    .await?;
+
+    // Store in DB; tickers that changed or disappeared keep their earlier versions
+    const VALUES: [&str; 2] = ["cik_str", "title"];
+
+    let rows: Vec<_> = res
+        .values()
+        .map(|ticker| -> db::CopyRow { vec![&ticker.ticker, &ticker.cik_str, &ticker.title] })
+        .collect();
 
-    Ok(transaction.commit().await?)
+    let check = db::Check {
+        min_rows: Settings::instance().reload.ticker_min_rows,
+        required: &["ticker", "cik_str", "title"],
+    };
+    let today = time::OffsetDateTime::now_utc().date();
+    let merged = db::merge_history("ticker", "ticker", &VALUES, &rows, &check, today).await?;
+
+    Ok(ListingRebuildRes {
+        as_of: today,
+        rows: merged.rows,
+        listed: merged.listed,
+        changed: merged.changed,
+        delisted: merged.delisted,
+    })
+}
+
+/// Put back the tickers replaced by the last build
+#[tracing::instrument(err)]
+pub async fn rollback_ticker_db() -> Result<()> {
+    db::swap_back("ticker").await
 }
 
 #[tracing::instrument]
 pub async fn get_ticker(search_word: &str) -> Result<Vec<Ticker>> {
-    const SQL: &str = "SELECT * FROM ticker WHERE title ILIKE $1 OR ticker ILIKE $1;";
+    const SQL: &str =
+        "SELECT * FROM ticker WHERE valid_to IS NULL AND (title ILIKE $1 OR ticker ILIKE $1);";
 
     let word = format!("%{}%", search_word);
 
@@ -58,3 +72,51 @@ pub async fn get_ticker(search_word: &str) -> Result<Vec<Ticker>> {
 
     Ok(res)
 }
+
+/// Every version of a ticker, oldest first
+#[tracing::instrument]
+pub async fn get_ticker_history(ticker: &str) -> Result<Vec<TickerVersionRes>> {
+    const SQL: &str = "SELECT * FROM ticker WHERE ticker=$1::VARCHAR ORDER BY valid_from;";
+
+    let rows = db::query(SQL, &[&ticker]).await?;
+    if rows.is_empty() {
+        return Err(Error::E404NotFound("ticker".into()));
+    }
+
+    let res = rows
+        .into_iter()
+        .map(|row| TickerVersionRes::from(&row))
+        .collect();
+
+    Ok(res)
+}
+
+/// Tickers listed or delisted between two dates; a version following another directly is a change, not a listing
+#[tracing::instrument]
+pub async fn get_ticker_listings(
+    from: time::Date,
+    to: time::Date,
+) -> Result<ListingsRes<TickerVersionRes>> {
+    const SQL_LISTED: &str = "
+        SELECT * FROM ticker c WHERE c.valid_from BETWEEN $1::DATE AND $2::DATE
+            AND NOT EXISTS (
+                SELECT 1 FROM ticker p WHERE p.ticker=c.ticker AND p.valid_to=c.valid_from
+            )
+        ORDER BY c.valid_from, c.ticker;";
+    const SQL_DELISTED: &str = "
+        SELECT * FROM ticker c WHERE c.valid_to BETWEEN $1::DATE AND $2::DATE
+            AND NOT EXISTS (
+                SELECT 1 FROM ticker n WHERE n.ticker=c.ticker AND n.valid_from=c.valid_to
+            )
+        ORDER BY c.valid_to, c.ticker;";
+
+    let listed = db::query(SQL_LISTED, &[&from, &to]).await?;
+    let delisted = db::query(SQL_DELISTED, &[&from, &to]).await?;
+
+    Ok(ListingsRes {
+        from,
+        to,
+        listed: listed.iter().map(TickerVersionRes::from).collect(),
+        delisted: delisted.iter().map(TickerVersionRes::from).collect(),
+    })
+}
@@ -1,9 +1,16 @@
+pub mod auth;
 pub mod cache;
+pub mod calendar;
 pub mod cors;
 pub mod datetime;
 pub mod db;
 pub mod error;
 pub mod hex;
+pub mod metrics;
+pub mod migrate;
+pub mod page;
+pub mod rate_limit;
+pub mod resample;
 pub mod settings;
 pub mod telemetry;
 
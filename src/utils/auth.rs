@@ -0,0 +1,113 @@
+use super::{error::Error, settings};
+use actix_web::{
+    HttpMessage,
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{Method, header},
+    middleware::Next,
+};
+use tracing::{Level, event};
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    ReadOnly,
+    Admin,
+}
+
+/// Client identified by its API key; available in request extensions after `authorize`
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub name: String,
+    pub role: Role,
+}
+
+/// Middleware: reads stay open; anything that mutates requires an admin key
+pub async fn authorize(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let app_settings = settings::Settings::instance();
+    if !app_settings.auth.enabled {
+        return next.call(req).await;
+    }
+
+    let is_read = matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS);
+    let client = match credential(&req).map(|key| identify(&key)) {
+        Some(Ok(c)) => Some(c),
+        // Reads are open anyway, so a wrong key is ignored rather than rejected
+        Some(Err(_)) if is_read => None,
+        Some(Err(e)) => return Err(e.into()),
+        None => None,
+    };
+
+    if !is_read {
+        match &client {
+            None => return Err(Error::E401Unauthorized("missing API key".into()).into()),
+            Some(c) if c.role != Role::Admin => {
+                return Err(Error::E403Forbidden("admin role is required".into()).into());
+            }
+            Some(c) => event!(Level::INFO, "{} {} by {}", req.method(), req.path(), c.name),
+        }
+    }
+
+    if let Some(c) = client {
+        req.extensions_mut().insert(c);
+    }
+
+    next.call(req).await
+}
+
+/// Client of a valid key, if the request has one
+pub(crate) fn client(req: &ServiceRequest) -> Option<Client> {
+    credential(req).and_then(|key| identify(&key).ok())
+}
+
+/// Key from `Authorization: Bearer <key>` or `X-API-Key: <key>`
+fn credential(req: &ServiceRequest) -> Option<String> {
+    let headers = req.headers();
+    if let Some(v) = headers.get(header::AUTHORIZATION)
+        && let Ok(s) = v.to_str()
+        && let Some(token) = s.strip_prefix("Bearer ")
+    {
+        return Some(token.trim().to_string());
+    }
+    headers
+        .get("X-API-Key")
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.trim().to_string())
+}
+
+fn identify(key: &str) -> Result<Client, Error> {
+    let app_settings = settings::Settings::instance();
+    app_settings
+        .auth
+        .keys
+        .iter()
+        .find(|k| constant_time_eq(k.key.as_bytes(), key.as_bytes()))
+        .map(|k| Client {
+            name: k.name.clone(),
+            role: k.role,
+        })
+        .ok_or_else(|| Error::E401Unauthorized("invalid API key".into()))
+}
+
+/// Compare without short-circuiting so that timing does not leak how much of a key matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compare_keys() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
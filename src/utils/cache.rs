@@ -1,12 +1,57 @@
-use super::{settings, Result};
+use super::{Result, error::Error, metrics, settings};
 use deadpool_redis::{
-    redis::{AsyncCommands, FromRedisValue, ToSingleRedisArg},
     Pool,
+    redis::{self, AsyncCommands},
 };
+use std::io::Write;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+use tracing::{Level, event};
 
 #[allow(unused)]
 static POOL: std::sync::OnceLock<Pool> = std::sync::OnceLock::new();
 
+// Marker byte in front of every value written by `set_json`
+const PLAIN: u8 = b'j';
+const DEFLATE: u8 = b'z';
+
+// Redis is skipped while the breaker is open; it is tried again once the backoff elapses
+static BREAKER: Mutex<Breaker> = Mutex::new(Breaker {
+    failures: 0,
+    open_until: None,
+});
+
+// Raw bytes as they would have been stored in Redis, with their expiry
+static FALLBACK: Mutex<std::collections::BTreeMap<String, (Vec<u8>, Instant)>> =
+    Mutex::new(std::collections::BTreeMap::new());
+
+// Fetches in flight on this instance, one lock per cache key
+static FLIGHTS: Mutex<std::collections::BTreeMap<String, Weak<Flight>>> =
+    Mutex::new(std::collections::BTreeMap::new());
+
+// Holds the error of a failed fetch so that callers waiting on it do not fetch again; the flight is
+// then forgotten, so later callers fetch anew
+type Flight = tokio::sync::Mutex<Option<Error>>;
+
+// Releases the lock only if it is still held by the caller's token
+const UNLOCK_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+  return redis.call('DEL', KEYS[1])
+end
+return 0
+";
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry<T> {
+    fresh_until: u64, // unix time in sec
+    value: T,
+}
+
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
 #[tracing::instrument]
 pub fn pool() -> &'static Pool {
     POOL.get_or_init(|| {
@@ -18,39 +63,497 @@ pub fn pool() -> &'static Pool {
     })
 }
 
-#[tracing::instrument(err)]
-pub async fn set<V>(k: &str, v: &V) -> Result<()>
+/// Cache a value as JSON for a day
+#[allow(unused)]
+#[tracing::instrument(skip(v), err)]
+pub async fn set_json<T: serde::Serialize>(k: &str, v: &T) -> Result<()> {
+    set_json_with_timer(k, v, 86400).await // 86400 = 24*60*60 : 1 day in sec
+}
+
+#[tracing::instrument(skip(v), err)]
+pub async fn set_json_with_timer<T: serde::Serialize>(k: &str, v: &T, seconds: u64) -> Result<()> {
+    let app_settings = settings::Settings::instance();
+    let compress_min_bytes = app_settings
+        .cache
+        .compress
+        .then_some(app_settings.cache.compress_min_bytes);
+
+    let bytes = encode(v, compress_min_bytes)?;
+    set_bytes(&json_key(k), bytes, seconds).await;
+    Ok(())
+}
+
+/// Get a value stored by `set_json`; an entry that fails to decode is treated as a miss
+#[tracing::instrument]
+pub async fn get_json<T: serde::de::DeserializeOwned>(k: &str) -> Option<T> {
+    let key = json_key(k);
+    let bytes = get_bytes(&key).await?;
+
+    match decode(&bytes) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            metrics::count_cache("decode_error");
+            event!(Level::WARN, "ignoring cache entry {}: {}", key, e);
+            None
+        }
+    }
+}
+
+/// Get a cached value or fetch and cache it under the expiry `policy`, e.g. `cache::policy("dart")`.
+/// A stale entry is served as is while it is refreshed in the background; concurrent callers for
+/// the same key share one fetch, within this instance through a local lock and across instances
+/// through a Redis lock
+#[tracing::instrument(skip(fetch), err)]
+pub async fn get_or_fetch_json<T, F, Fut>(
+    k: &str,
+    policy: settings::CachePolicy,
+    fetch: F,
+) -> Result<T>
 where
-    V: ToSingleRedisArg + std::marker::Sync + std::fmt::Debug,
+    T: serde::Serialize + serde::de::DeserializeOwned + Send + Sync + 'static,
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<T>> + Send + 'static,
 {
+    if let Some(entry) = get_json::<Entry<T>>(k).await {
+        if entry.fresh_until <= now_secs() {
+            refresh_in_background(k.to_string(), policy, fetch);
+        }
+        return Ok(entry.value);
+    }
+
+    let flight = local_flight(k);
+    let mut failed = flight.lock().await;
+    if let Some(e) = failed.as_ref() {
+        return Err(e.share());
+    }
+    if let Some(entry) = get_json::<Entry<T>>(k).await {
+        return Ok(entry.value);
+    }
+
+    let app_settings = settings::Settings::instance();
+    let lock_key = format!("lock:{}", json_key(k));
+    let token = uuid::Uuid::new_v4().to_string();
+
+    // `None` means Redis is unavailable; the local lock is all there is then
+    if let Some(false) = try_lock(&lock_key, &token, app_settings.cache.lock_ttl_ms).await {
+        let deadline = Instant::now() + Duration::from_millis(app_settings.cache.lock_wait_ms);
+        while Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            if let Some(entry) = get_json::<Entry<T>>(k).await {
+                return Ok(entry.value);
+            }
+        }
+        event!(
+            Level::WARN,
+            "gave up waiting for {}, fetching anyway",
+            lock_key
+        );
+    }
+
+    let res = fetch().await;
+    match &res {
+        // The value is good even if it could not be cached
+        Ok(v) => {
+            if let Err(e) = set_entry(k, v, &policy).await {
+                event!(Level::WARN, "failed to cache {}: {}", k, e);
+            }
+        }
+        Err(e) => {
+            *failed = Some(e.share());
+            end_flight(k, &flight);
+        }
+    }
+    unlock(&lock_key, &token).await;
+    res
+}
+
+/// Whether an action keyed by `k` may run now; if so, it may not run again for `seconds`
+#[tracing::instrument]
+pub async fn throttle(k: &str, seconds: u64) -> bool {
+    let k = format!("throttle:{}", k);
+    if let Some(acquired) = try_lock(&k, "1", seconds * 1000).await {
+        return acquired;
+    }
+
+    // Redis is unavailable; throttle within this instance
+    let key = get_key_prefixed(&k);
+    if get_fallback(&key).is_some() {
+        return false;
+    }
+    set_fallback(key, Vec::new(), Duration::from_secs(seconds));
+    true
+}
+
+/// Check that Redis answers; fails right away while the breaker is open
+pub async fn ping() -> Result<()> {
+    call_redis(async {
+        let mut conn = pool().get().await?;
+        redis::cmd("PING").query_async::<String>(&mut conn).await?;
+        Ok(())
+    })
+    .await
+    .ok_or_else(|| Error::General("redis is unavailable".into()))
+}
+
+pub fn get_key_prefixed(k: &str) -> String {
+    let app_name = env!("CARGO_PKG_NAME");
+    let app_version = env!("CARGO_PKG_VERSION");
+    format!("{}_{}:{}", app_name, app_version, k)
+}
+
+/// Run a Redis call unless the breaker is open; `None` means Redis could not be used
+pub(crate) async fn call_redis<T>(f: impl Future<Output = Result<T>>) -> Option<T> {
+    let app_settings = settings::Settings::instance();
+    if BREAKER
+        .lock()
+        .unwrap()
+        .open_until
+        .is_some_and(|t| Instant::now() < t)
+    {
+        return None;
+    }
+
+    let timeout = Duration::from_millis(app_settings.cache.timeout_ms);
+    let res = match tokio::time::timeout(timeout, f).await {
+        Ok(r) => r.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("timed out after {:?}", timeout)),
+    };
+
+    let mut breaker = BREAKER.lock().unwrap();
+    match res {
+        Ok(v) => {
+            if breaker.failures > 0 {
+                event!(Level::INFO, "redis is available again");
+                breaker.failures = 0;
+                breaker.open_until = None;
+                FALLBACK.lock().unwrap().clear();
+            }
+            Some(v)
+        }
+        Err(e) => {
+            metrics::count_cache("error");
+            breaker.failures += 1;
+            let wait = backoff(
+                breaker.failures,
+                app_settings.cache.backoff_min_secs,
+                app_settings.cache.backoff_max_secs,
+            );
+            breaker.open_until = Some(Instant::now() + wait);
+            event!(
+                Level::WARN,
+                "redis unavailable, bypassing cache for {:?}: {}",
+                wait,
+                e
+            );
+            None
+        }
+    }
+}
+
+// Bump `cache.schema_version` when cached models change shape so that old entries are ignored
+fn json_key(k: &str) -> String {
+    let app_settings = settings::Settings::instance();
+    format!("json:v{}:{}", app_settings.cache.schema_version, k)
+}
+
+/// Read raw bytes; a Redis failure is treated as a miss unless the in-process fallback has it
+async fn get_bytes(k: &str) -> Option<Vec<u8>> {
     let key = get_key_prefixed(k);
-    let mut conn = pool().get().await?;
-    conn.set_ex::<&str, &V, ()>(&key, v, 86400).await?; // 86400 = 24*60*60 : 1 day in sec
-    Ok(())
+    let value = call_redis(async {
+        let mut conn = pool().get().await?;
+        Ok(conn.get::<&str, Option<Vec<u8>>>(&key).await?)
+    })
+    .await;
+
+    let value = match value {
+        Some(v) => v,
+        None => {
+            let v = get_fallback(&key);
+            if v.is_some() {
+                metrics::count_cache("fallback_hit");
+                return v;
+            }
+            None
+        }
+    };
+    metrics::count_cache(if value.is_some() { "hit" } else { "miss" });
+    value
 }
 
-#[allow(unused)]
-#[tracing::instrument(err)]
-pub async fn set_with_timer<V>(k: &str, v: &V, seconds: u64) -> Result<()>
+/// Write raw bytes; kept in the in-process fallback when Redis cannot be used
+async fn set_bytes(k: &str, bytes: Vec<u8>, seconds: u64) {
+    let key = get_key_prefixed(k);
+    let stored = call_redis(async {
+        let mut conn = pool().get().await?;
+        conn.set_ex::<&str, &[u8], ()>(&key, &bytes, seconds)
+            .await?;
+        Ok(())
+    })
+    .await;
+
+    if stored.is_none() {
+        set_fallback(key, bytes, Duration::from_secs(seconds));
+    }
+}
+
+/// JSON prefixed by a marker byte; deflated when it is at least `compress_min_bytes` long
+fn encode<T: serde::Serialize>(v: &T, compress_min_bytes: Option<usize>) -> Result<Vec<u8>> {
+    let json = serde_json::to_vec(v)?;
+
+    match compress_min_bytes {
+        Some(n) if json.len() >= n => {
+            let mut encoder =
+                flate2::write::DeflateEncoder::new(vec![DEFLATE], flate2::Compression::fast());
+            encoder.write_all(&json)?;
+            Ok(encoder.finish()?)
+        }
+        _ => {
+            let mut buf = Vec::with_capacity(json.len() + 1);
+            buf.push(PLAIN);
+            buf.extend(json);
+            Ok(buf)
+        }
+    }
+}
+
+fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    match bytes.split_first() {
+        Some((&PLAIN, json)) => Ok(serde_json::from_slice(json)?),
+        Some((&DEFLATE, data)) => Ok(serde_json::from_reader(flate2::read::DeflateDecoder::new(
+            data,
+        ))?),
+        _ => Err("unknown cache encoding".into()),
+    }
+}
+
+/// Expiry policy configured for a family of keys in `Settings.cache.policies`
+pub fn policy(name: &str) -> settings::CachePolicy {
+    let app_settings = settings::Settings::instance();
+    app_settings
+        .cache
+        .policies
+        .get(name)
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Fresh until `fresh_secs` have passed, then stale until Redis expires it after `stale_secs` more
+async fn set_entry<T: serde::Serialize>(
+    k: &str,
+    v: &T,
+    policy: &settings::CachePolicy,
+) -> Result<()> {
+    let entry = Entry {
+        fresh_until: now_secs() + policy.fresh_secs,
+        value: v,
+    };
+    set_json_with_timer(k, &entry, policy.fresh_secs + policy.stale_secs).await
+}
+
+/// Refresh a stale entry unless this or another instance is already doing so
+fn refresh_in_background<T, F, Fut>(k: String, policy: settings::CachePolicy, fetch: F)
 where
-    V: ToSingleRedisArg + std::marker::Sync + std::fmt::Debug,
+    T: serde::Serialize + Send + Sync + 'static,
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<T>> + Send + 'static,
 {
+    tokio::spawn(async move {
+        let flight = local_flight(&k);
+        let Ok(_guard) = flight.try_lock() else {
+            return;
+        };
+
+        let app_settings = settings::Settings::instance();
+        let lock_key = format!("lock:{}", json_key(&k));
+        let token = uuid::Uuid::new_v4().to_string();
+        if let Some(false) = try_lock(&lock_key, &token, app_settings.cache.lock_ttl_ms).await {
+            return;
+        }
+
+        let res = match fetch().await {
+            Ok(v) => set_entry(&k, &v, &policy).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = res {
+            event!(Level::WARN, "failed to refresh {}: {}", k, e);
+        }
+        unlock(&lock_key, &token).await;
+    });
+}
+
+fn local_flight(k: &str) -> Arc<Flight> {
+    let mut flights = FLIGHTS.lock().unwrap();
+    if let Some(flight) = flights.get(k).and_then(Weak::upgrade) {
+        return flight;
+    }
+
+    flights.retain(|_, f| f.strong_count() > 0);
+    let flight = Arc::new(tokio::sync::Mutex::new(None));
+    flights.insert(k.to_string(), Arc::downgrade(&flight));
+    flight
+}
+
+fn end_flight(k: &str, flight: &Arc<Flight>) {
+    let mut flights = FLIGHTS.lock().unwrap();
+    if flights
+        .get(k)
+        .is_some_and(|f| std::ptr::eq(f.as_ptr(), Arc::as_ptr(flight)))
+    {
+        flights.remove(k);
+    }
+}
+
+/// `Some(true)` if the lock was taken, `Some(false)` if another caller holds it
+async fn try_lock(k: &str, token: &str, ttl_ms: u64) -> Option<bool> {
     let key = get_key_prefixed(k);
-    let mut conn = pool().get().await?;
-    conn.set_ex::<&str, &V, ()>(&key, v, seconds).await?;
-    Ok(())
+    call_redis(async {
+        let mut conn = pool().get().await?;
+        let res = redis::cmd("SET")
+            .arg(&key)
+            .arg(token)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl_ms)
+            .query_async::<Option<String>>(&mut conn)
+            .await?;
+        Ok(res.is_some())
+    })
+    .await
 }
 
-#[tracing::instrument(err)]
-pub async fn get<T: FromRedisValue>(k: &str) -> Result<T> {
+async fn unlock(k: &str, token: &str) {
     let key = get_key_prefixed(k);
-    let mut conn = pool().get().await?;
-    let res = conn.get::<&str, T>(&key).await?;
-    Ok(res)
+    call_redis(async {
+        let mut conn = pool().get().await?;
+        redis::cmd("EVAL")
+            .arg(UNLOCK_SCRIPT)
+            .arg(1)
+            .arg(&key)
+            .arg(token)
+            .query_async::<i64>(&mut conn)
+            .await?;
+        Ok(())
+    })
+    .await;
 }
 
-fn get_key_prefixed(k: &str) -> String {
-    let app_name = env!("CARGO_PKG_NAME");
-    let app_version = env!("CARGO_PKG_VERSION");
-    format!("{}_{}:{}", app_name, app_version, k)
+/// Exponential backoff doubling from `min_secs` on every consecutive failure, capped at `max_secs`
+fn backoff(failures: u32, min_secs: u64, max_secs: u64) -> Duration {
+    let exp = failures.saturating_sub(1).min(31);
+    Duration::from_secs(min_secs.saturating_mul(1 << exp).min(max_secs))
+}
+
+fn set_fallback(key: String, bytes: Vec<u8>, ttl: Duration) {
+    let max_entries = settings::Settings::instance().cache.fallback_max_entries;
+    if max_entries == 0 {
+        return;
+    }
+
+    let now = Instant::now();
+    let mut map = FALLBACK.lock().unwrap();
+    if map.len() >= max_entries {
+        map.retain(|_, (_, expiry)| *expiry > now);
+    }
+    if map.len() >= max_entries
+        && let Some(k) = map
+            .iter()
+            .min_by_key(|(_, (_, expiry))| *expiry)
+            .map(|(k, _)| k.clone())
+    {
+        map.remove(&k);
+    }
+    map.insert(key, (bytes, now + ttl));
+}
+
+fn get_fallback(key: &str) -> Option<Vec<u8>> {
+    let map = FALLBACK.lock().unwrap();
+    map.get(key)
+        .filter(|(_, expiry)| *expiry > Instant::now())
+        .map(|(bytes, _)| bytes.clone())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        assert_eq!(backoff(1, 1, 60), Duration::from_secs(1));
+        assert_eq!(backoff(2, 1, 60), Duration::from_secs(2));
+        assert_eq!(backoff(4, 1, 60), Duration::from_secs(8));
+        assert_eq!(backoff(10, 1, 60), Duration::from_secs(60));
+        assert_eq!(backoff(100, 1, 60), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let value = vec!["005930".to_string(); 100];
+
+        let plain = encode(&value, None).unwrap();
+        assert_eq!(plain[0], PLAIN);
+        assert_eq!(decode::<Vec<String>>(&plain).unwrap(), value);
+
+        let deflated = encode(&value, Some(16)).unwrap();
+        assert_eq!(deflated[0], DEFLATE);
+        assert!(deflated.len() < plain.len());
+        assert_eq!(decode::<Vec<String>>(&deflated).unwrap(), value);
+    }
+
+    #[test]
+    fn decode_corrupted() {
+        assert!(decode::<Vec<String>>(b"").is_err());
+        assert!(decode::<Vec<String>>(b"j[\"005930\"").is_err());
+        assert!(decode::<Vec<String>>(b"{\"key\":1}").is_err());
+        assert!(decode::<Vec<String>>(b"z\x00\x01").is_err());
+    }
+
+    #[tokio::test]
+    async fn waiters_share_failed_fetch() {
+        let k = format!("test:{}", uuid::Uuid::new_v4());
+        let fetches = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let get = |delay_ms: u64| {
+            let k = k.clone();
+            let fetches = fetches.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                get_or_fetch_json::<String, _, _>(&k, policy("test"), move || async move {
+                    fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(200)).await;
+                    Err(Error::E404NotFound("test".into()))
+                })
+                .await
+            }
+        };
+
+        let (leader, waiter) = tokio::join!(get(0), get(50));
+        assert!(matches!(leader, Err(Error::E404NotFound(_))));
+        assert!(matches!(waiter, Err(Error::E404NotFound(_))));
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn fetches_again_after_failed_flight() {
+        let k = format!("test:{}", uuid::Uuid::new_v4());
+        let fetches = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let get = || {
+            let fetches = fetches.clone();
+            get_or_fetch_json::<String, _, _>(&k, policy("test"), move || async move {
+                fetches.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Err(Error::E404NotFound("test".into()))
+            })
+        };
+
+        // Another caller still holding the flight keeps it alive
+        let _held = local_flight(&k);
+        assert!(get().await.is_err());
+        assert!(get().await.is_err());
+        assert_eq!(fetches.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
 }
@@ -0,0 +1,448 @@
+use super::{error::Error, settings};
+use time::{
+    Date, Duration, Month, OffsetDateTime, UtcOffset, Weekday,
+    macros::{date, offset, time},
+};
+
+const KST: UtcOffset = offset!(+9);
+const EST: UtcOffset = offset!(-5);
+const EDT: UtcOffset = offset!(-4);
+
+/// Exchanges whose trading sessions decide how long quotes are cached
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Market {
+    Krx,
+    Us, // NYSE and Nasdaq share one calendar
+}
+
+// KRX holidays that do not fall on a fixed date: lunar holidays, substitute holidays, elections and
+// one-off closures. Dates beyond this table can be added with `calendar.krx_holidays`; a year in
+// neither is not known
+const KRX_HOLIDAYS: &[Date] = &[
+    // 2024
+    date!(2024 - 02 - 09),
+    date!(2024 - 02 - 12),
+    date!(2024 - 04 - 10),
+    date!(2024 - 05 - 06),
+    date!(2024 - 05 - 15),
+    date!(2024 - 09 - 16),
+    date!(2024 - 09 - 17),
+    date!(2024 - 09 - 18),
+    date!(2024 - 10 - 01),
+    // 2025
+    date!(2025 - 01 - 27),
+    date!(2025 - 01 - 28),
+    date!(2025 - 01 - 29),
+    date!(2025 - 01 - 30),
+    date!(2025 - 03 - 03),
+    date!(2025 - 05 - 06),
+    date!(2025 - 06 - 03),
+    date!(2025 - 10 - 06),
+    date!(2025 - 10 - 07),
+    date!(2025 - 10 - 08),
+    // 2026
+    date!(2026 - 02 - 16),
+    date!(2026 - 02 - 17),
+    date!(2026 - 02 - 18),
+    date!(2026 - 03 - 02),
+    date!(2026 - 05 - 25),
+    date!(2026 - 06 - 03),
+    date!(2026 - 08 - 17),
+    date!(2026 - 09 - 24),
+    date!(2026 - 09 - 25),
+    date!(2026 - 10 - 05),
+    // 2027
+    date!(2027 - 02 - 05),
+    date!(2027 - 02 - 08),
+    date!(2027 - 05 - 13),
+    date!(2027 - 08 - 16),
+    date!(2027 - 09 - 14),
+    date!(2027 - 09 - 15),
+    date!(2027 - 09 - 16),
+    date!(2027 - 10 - 04),
+    date!(2027 - 10 - 11),
+    date!(2027 - 12 - 27),
+];
+
+static EXTRA_HOLIDAYS: std::sync::OnceLock<(Vec<Date>, Vec<Date>)> = std::sync::OnceLock::new();
+
+/// Trading hours of one day in the exchange's local time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Session {
+    pub open: OffsetDateTime,
+    pub close: OffsetDateTime,
+    pub half_day: bool, // closes early
+}
+
+impl std::str::FromStr for Market {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "krx" => Ok(Self::Krx),
+            "us" | "nyse" | "nasdaq" => Ok(Self::Us),
+            _ => Err(Error::E400BadRequest(format!("unknown market: {}", s))),
+        }
+    }
+}
+
+impl Market {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Krx => "krx",
+            Self::Us => "us",
+        }
+    }
+
+    /// IANA time zone of the exchange
+    pub fn timezone(&self) -> &'static str {
+        match self {
+            Self::Krx => "Asia/Seoul",
+            Self::Us => "America/New_York",
+        }
+    }
+
+    /// Offset of the exchange's local time on a local `date`
+    fn offset(&self, date: Date) -> UtcOffset {
+        match self {
+            Self::Krx => KST,
+            Self::Us if is_us_dst(date) => EDT,
+            Self::Us => EST,
+        }
+    }
+
+    /// `at` in the exchange's local time
+    pub fn local(&self, at: OffsetDateTime) -> OffsetDateTime {
+        // The date in standard time is good enough to tell DST apart outside of 2 a.m. on a Sunday
+        let standard = match self {
+            Self::Krx => KST,
+            Self::Us => EST,
+        };
+        at.to_offset(self.offset(at.to_offset(standard).date()))
+    }
+
+    /// Local midnight at the start of `date`
+    pub fn start_of_day(&self, date: Date) -> OffsetDateTime {
+        date.midnight().assume_offset(self.offset(date))
+    }
+
+    pub fn is_trading_day(&self, date: Date) -> bool {
+        !matches!(date.weekday(), Weekday::Saturday | Weekday::Sunday) && !self.is_holiday(date)
+    }
+
+    pub fn is_holiday(&self, date: Date) -> bool {
+        let (krx, us) = extra_holidays();
+        match self {
+            Self::Krx => is_krx_holiday(date) || krx.contains(&date),
+            Self::Us => is_us_holiday(date) || us.contains(&date),
+        }
+    }
+
+    /// Whether the holidays of the year of `date` are known; US holidays follow fixed rules
+    pub fn is_known(&self, date: Date) -> bool {
+        let (krx, _) = extra_holidays();
+        match self {
+            Self::Krx => KRX_HOLIDAYS
+                .iter()
+                .chain(krx)
+                .any(|d| d.year() == date.year()),
+            Self::Us => true,
+        }
+    }
+
+    /// Trading hours on a local `date`; `None` if the market is closed all day
+    pub fn session(&self, date: Date) -> Option<Session> {
+        if !self.is_trading_day(date) {
+            return None;
+        }
+
+        let (open, close, half_day) = match self {
+            // The first trading day of the year opens an hour late
+            Self::Krx if self.is_first_trading_day_of_year(date) => {
+                (time!(10:00), time!(15:30), false)
+            }
+            Self::Krx => (time!(9:00), time!(15:30), false),
+            Self::Us if is_us_half_day(date) => (time!(9:30), time!(13:00), true),
+            Self::Us => (time!(9:30), time!(16:00), false),
+        };
+        let offset = self.offset(date);
+        Some(Session {
+            open: date.with_time(open).assume_offset(offset),
+            close: date.with_time(close).assume_offset(offset),
+            half_day,
+        })
+    }
+
+    pub fn is_open(&self, at: OffsetDateTime) -> bool {
+        self.session(self.local(at).date())
+            .is_some_and(|s| s.open <= at && at < s.close)
+    }
+
+    /// The next time the market opens after `at`; `at` itself if it is the opening time, `None`
+    /// past the last representable date
+    pub fn next_open(&self, at: OffsetDateTime) -> Option<OffsetDateTime> {
+        let mut date = self.local(at).date();
+        loop {
+            if let Some(s) = self.session(date)
+                && s.open >= at
+            {
+                return Some(s.open);
+            }
+            date = date.next_day()?;
+        }
+    }
+
+    /// The latest trading day whose session had closed by `at`
+    pub fn last_completed_trading_day(&self, at: OffsetDateTime) -> Date {
+        let date = self.local(at).date();
+        match self.session(date) {
+            Some(s) if s.close <= at => date,
+            _ => self.previous_trading_day(date),
+        }
+    }
+
+    /// The trading day before `date`
+    pub fn previous_trading_day(&self, date: Date) -> Date {
+        let mut date = date.previous_day().unwrap();
+        while !self.is_trading_day(date) {
+            date = date.previous_day().unwrap();
+        }
+        date
+    }
+
+    fn is_first_trading_day_of_year(&self, date: Date) -> bool {
+        self.is_trading_day(date)
+            && (1..date.ordinal()).all(|day| {
+                Date::from_ordinal_date(date.year(), day).is_ok_and(|d| !self.is_trading_day(d))
+            })
+    }
+}
+
+/// Seconds to cache a quote fetched at `at`: briefly while the market is open, otherwise until it opens
+pub fn quote_ttl(market: Market, at: OffsetDateTime) -> u64 {
+    let app_settings = settings::Settings::instance();
+    if market.is_open(at) {
+        return app_settings.calendar.quote_ttl_open_secs;
+    }
+
+    let until_open = market
+        .next_open(at)
+        .map_or(0, |open| (open - at).whole_seconds());
+    u64::try_from(until_open)
+        .unwrap_or_default()
+        .max(app_settings.calendar.quote_ttl_open_secs)
+}
+
+fn extra_holidays() -> &'static (Vec<Date>, Vec<Date>) {
+    EXTRA_HOLIDAYS.get_or_init(|| {
+        let app_settings = settings::Settings::instance();
+        (
+            parse_dates(&app_settings.calendar.krx_holidays),
+            parse_dates(&app_settings.calendar.us_holidays),
+        )
+    })
+}
+
+fn parse_dates(dates: &[String]) -> Vec<Date> {
+    let format = time::macros::format_description!("[year]-[month]-[day]");
+    dates
+        .iter()
+        .filter_map(|s| Date::parse(s, &format).ok())
+        .collect()
+}
+
+// Fixed-date closures; the rest is in `KRX_HOLIDAYS`
+fn is_krx_holiday(date: Date) -> bool {
+    let fixed = matches!(
+        (date.month(), date.day()),
+        (Month::January, 1)
+            | (Month::March, 1)
+            | (Month::May, 1)
+            | (Month::May, 5)
+            | (Month::June, 6)
+            | (Month::August, 15)
+            | (Month::October, 3)
+            | (Month::October, 9)
+            | (Month::December, 25)
+            | (Month::December, 31) // year-end closing
+    );
+    fixed || KRX_HOLIDAYS.contains(&date)
+}
+
+// NYSE holiday rules; a holiday on Saturday is observed on Friday and on Sunday on Monday,
+// except that New Year's Day on Saturday is not observed at all
+fn is_us_holiday(date: Date) -> bool {
+    let year = date.year();
+    let observed = |month: Month, day: u8| {
+        let d = Date::from_calendar_date(year, month, day).unwrap();
+        match d.weekday() {
+            Weekday::Saturday => d.previous_day().unwrap_or(d),
+            Weekday::Sunday => d.next_day().unwrap_or(d),
+            _ => d,
+        }
+    };
+
+    let new_year = Date::from_calendar_date(year, Month::January, 1).unwrap();
+    let mut holidays = vec![
+        nth_weekday(year, Month::January, Weekday::Monday, 3), // Martin Luther King Jr. Day
+        nth_weekday(year, Month::February, Weekday::Monday, 3), // Washington's Birthday
+        easter(year) - Duration::days(2),                      // Good Friday
+        last_weekday(year, Month::May, Weekday::Monday),       // Memorial Day
+        observed(Month::July, 4),                              // Independence Day
+        nth_weekday(year, Month::September, Weekday::Monday, 1), // Labor Day
+        nth_weekday(year, Month::November, Weekday::Thursday, 4), // Thanksgiving Day
+        observed(Month::December, 25),                         // Christmas Day
+    ];
+    if new_year.weekday() != Weekday::Saturday {
+        holidays.push(observed(Month::January, 1));
+    }
+    if year >= 2022 {
+        holidays.push(observed(Month::June, 19)); // Juneteenth
+    }
+
+    holidays.contains(&date)
+}
+
+// NYSE closes at 13:00 on the day before Independence Day, the day after Thanksgiving and
+// Christmas Eve, unless that day is itself a holiday or a Friday before a long weekend
+fn is_us_half_day(date: Date) -> bool {
+    let year = date.year();
+    let mon_to_thu = !matches!(
+        date.weekday(),
+        Weekday::Friday | Weekday::Saturday | Weekday::Sunday
+    );
+
+    match (date.month(), date.day()) {
+        (Month::July, 3) | (Month::December, 24) => mon_to_thu,
+        _ => date == nth_weekday(year, Month::November, Weekday::Thursday, 4) + Duration::days(1),
+    }
+}
+
+// From the second Sunday of March until the first Sunday of November
+fn is_us_dst(date: Date) -> bool {
+    let start = nth_weekday(date.year(), Month::March, Weekday::Sunday, 2);
+    let end = nth_weekday(date.year(), Month::November, Weekday::Sunday, 1);
+    date >= start && date < end
+}
+
+fn nth_weekday(year: i32, month: Month, weekday: Weekday, n: u8) -> Date {
+    let first = Date::from_calendar_date(year, month, 1).unwrap();
+    let offset =
+        (7 + weekday.number_days_from_monday() - first.weekday().number_days_from_monday()) % 7;
+    first + Duration::days(i64::from(offset + 7 * (n - 1)))
+}
+
+fn last_weekday(year: i32, month: Month, weekday: Weekday) -> Date {
+    let last = Date::from_calendar_date(year, month, month.length(year)).unwrap();
+    let offset =
+        (7 + last.weekday().number_days_from_monday() - weekday.number_days_from_monday()) % 7;
+    last - Duration::days(i64::from(offset))
+}
+
+// Anonymous Gregorian algorithm
+fn easter(year: i32) -> Date {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    Date::from_calendar_date(year, Month::try_from(month as u8).unwrap(), day as u8).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn us_holidays() {
+        assert_eq!(easter(2025), date!(2025 - 04 - 20));
+        assert_eq!(easter(2026), date!(2026 - 04 - 05));
+        assert!(is_us_holiday(date!(2025 - 04 - 18))); // Good Friday
+        assert!(is_us_holiday(date!(2025 - 11 - 27))); // Thanksgiving
+        assert!(is_us_holiday(date!(2026 - 07 - 03))); // Independence Day observed on Friday
+        assert!(is_us_holiday(date!(2027 - 06 - 18))); // Juneteenth observed on Friday
+        assert!(!is_us_holiday(date!(2021 - 12 - 31))); // New Year's Day on Saturday
+        assert!(!is_us_holiday(date!(2025 - 11 - 28)));
+    }
+
+    #[test]
+    fn krx_holidays() {
+        assert!(is_krx_holiday(date!(2025 - 01 - 29))); // Seollal
+        assert!(is_krx_holiday(date!(2025 - 12 - 31))); // year-end closing
+        assert!(!is_krx_holiday(date!(2025 - 01 - 31)));
+        assert!(Market::Krx.is_known(date!(2027 - 12 - 31)));
+        assert!(!Market::Krx.is_known(date!(2031 - 01 - 02)));
+        assert!(Market::Us.is_known(date!(2031 - 01 - 02)));
+    }
+
+    #[test]
+    fn sessions() {
+        // 09:00 KST
+        assert!(Market::Krx.is_open(datetime!(2025-06-02 00:00 UTC)));
+        assert!(!Market::Krx.is_open(datetime!(2025-06-02 06:30 UTC)));
+        // 09:30 EDT and EST
+        assert!(Market::Us.is_open(datetime!(2025-06-02 13:30 UTC)));
+        assert!(!Market::Us.is_open(datetime!(2025-12-01 13:30 UTC)));
+        assert!(Market::Us.is_open(datetime!(2025-12-01 14:30 UTC)));
+    }
+
+    #[test]
+    fn half_days() {
+        assert!(is_us_half_day(date!(2025 - 07 - 03)));
+        assert!(is_us_half_day(date!(2025 - 11 - 28)));
+        assert!(is_us_half_day(date!(2025 - 12 - 24)));
+        assert!(!is_us_half_day(date!(2026 - 07 - 03))); // Independence Day observed
+        assert!(!is_us_half_day(date!(2027 - 12 - 24))); // Christmas Day observed
+        let session = Market::Us.session(date!(2025 - 11 - 28)).unwrap();
+        assert_eq!(session.close, datetime!(2025-11-28 13:00 -5));
+        let session = Market::Krx.session(date!(2026 - 01 - 02)).unwrap();
+        assert_eq!(session.open, datetime!(2026-01-02 10:00 +9));
+    }
+
+    #[test]
+    fn last_completed_trading_day() {
+        // Monday before the open, then after the close
+        assert_eq!(
+            Market::Krx.last_completed_trading_day(datetime!(2025-06-09 08:00 +9)),
+            date!(2025 - 06 - 05)
+        );
+        assert_eq!(
+            Market::Krx.last_completed_trading_day(datetime!(2025-06-09 15:30 +9)),
+            date!(2025 - 06 - 09)
+        );
+        // Early close on the day after Thanksgiving
+        assert_eq!(
+            Market::Us.last_completed_trading_day(datetime!(2025-11-28 14:00 -5)),
+            date!(2025 - 11 - 28)
+        );
+    }
+
+    #[test]
+    fn next_open_skips_weekends_and_holidays() {
+        // Thursday after the close, Friday is Independence Day observed
+        assert_eq!(
+            Market::Us.next_open(datetime!(2026-07-02 21:00 UTC)),
+            Some(datetime!(2026-07-06 09:30 -4))
+        );
+        // Friday after the close, then Seollal
+        assert_eq!(
+            Market::Krx.next_open(datetime!(2026-02-13 07:00 UTC)),
+            Some(datetime!(2026-02-19 09:00 +9))
+        );
+        assert_eq!(
+            Market::Krx.next_open(datetime!(2026-02-19 00:00 UTC)),
+            Some(datetime!(2026-02-19 09:00 +9))
+        );
+        assert_eq!(Market::Us.next_open(datetime!(9999-12-31 22:00 UTC)), None);
+    }
+}
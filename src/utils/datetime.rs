@@ -1,3 +1,5 @@
+use super::settings::{Settings, WeekStart};
+
 // use super::error::Error;
 // use std::ops::Add;
 // use std::time::{SystemTime, UNIX_EPOCH};
@@ -28,12 +30,126 @@
 //     }
 // }
 
-pub fn get_sunday_of_week(date: &time::Date) -> crate::utils::Result<time::Date> {
-    Ok(time::Date::from_iso_week_date(
-        date.year(),
-        date.sunday_based_week(),
-        time::Weekday::Sunday,
-    )?)
+/// First day of the week that contains the date
+pub fn get_start_of_week(date: &time::Date, start: WeekStart) -> time::Date {
+    let first = match start {
+        WeekStart::Sunday => time::Weekday::Sunday,
+        WeekStart::Iso | WeekStart::Monday => time::Weekday::Monday,
+    };
+    let days = (date.weekday().number_days_from_sunday() + 7 - first.number_days_from_sunday()) % 7;
+    date.saturating_sub(time::Duration::days(i64::from(days)))
+}
+
+/// (year, week) of the week that contains the date
+///
+/// A week that spans two years belongs to only one of them. ISO weeks follow ISO 8601;
+/// otherwise the week that contains January 1 is week 1 of that year.
+pub fn get_week_of_year(date: &time::Date, start: WeekStart) -> (i32, u8) {
+    if start == WeekStart::Iso {
+        let (year, week, _) = date.to_iso_week_date();
+        return (year, week);
+    }
+
+    let first = get_start_of_week(date, start);
+    let last = first.saturating_add(time::Duration::days(6));
+    let jan_1 = last.replace_ordinal(1).unwrap_or(last);
+    let week = (first - get_start_of_week(&jan_1, start)).whole_weeks() + 1;
+    (last.year(), week as u8)
+}
+
+//==================== Aggregation Periods ====================
+
+/// Calendar periods that daily prices are aggregated into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+impl std::str::FromStr for Period {
+    type Err = super::error::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "weekly" => Ok(Self::Week),
+            "monthly" => Ok(Self::Month),
+            "quarterly" => Ok(Self::Quarter),
+            "yearly" => Ok(Self::Year),
+            _ => Err(super::error::Error::E400BadRequest(format!(
+                "unknown period: {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl Period {
+    pub const ALL: [Period; 4] = [Period::Week, Period::Month, Period::Quarter, Period::Year];
+
+    /// Periods rebuilt from stored daily prices rather than from a downloaded batch
+    pub const LONG: [Period; 3] = [Period::Month, Period::Quarter, Period::Year];
+
+    /// Suffix of the table and the endpoint
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Week => "weekly",
+            Self::Month => "monthly",
+            Self::Quarter => "quarterly",
+            Self::Year => "yearly",
+        }
+    }
+
+    /// Column that numbers the period within a year; a year needs none
+    pub fn column(&self) -> Option<&'static str> {
+        match self {
+            Self::Week => Some("week"),
+            Self::Month => Some("month"),
+            Self::Quarter => Some("quarter"),
+            Self::Year => None,
+        }
+    }
+
+    /// (year, number within the year) of the period that contains the date
+    pub fn key(&self, date: &time::Date) -> (i32, u8) {
+        match self {
+            Self::Week => get_week_of_year(date, Settings::instance().prices.week_start),
+            Self::Month => (date.year(), u8::from(date.month())),
+            Self::Quarter => (date.year(), (u8::from(date.month()) - 1) / 3 + 1),
+            Self::Year => (date.year(), 0),
+        }
+    }
+
+    /// First day of the period that contains the date
+    pub fn start(&self, date: &time::Date) -> crate::utils::Result<time::Date> {
+        let (year, number) = self.key(date);
+        Ok(match self {
+            Self::Week => get_start_of_week(date, Settings::instance().prices.week_start),
+            Self::Month => time::Date::from_calendar_date(year, date.month(), 1)?,
+            Self::Quarter => {
+                let month = time::Month::try_from((number - 1) * 3 + 1)?;
+                time::Date::from_calendar_date(year, month, 1)?
+            }
+            Self::Year => time::Date::from_calendar_date(year, time::Month::January, 1)?,
+        })
+    }
+}
+
+/// Split `from..=to` into consecutive ranges of at most `days` days
+pub fn date_chunks(from: time::Date, to: time::Date, days: u32) -> Vec<(time::Date, time::Date)> {
+    let step = time::Duration::days(i64::from(days.max(1)) - 1);
+    let mut chunks = Vec::new();
+    let mut start = from;
+    while start <= to {
+        let end = start.saturating_add(step).min(to);
+        chunks.push((start, end));
+        match end.next_day() {
+            Some(next) => start = next,
+            None => break,
+        }
+    }
+    chunks
 }
 
 // //==================== Time Serialize/Deserialize ====================
@@ -284,4 +400,75 @@ mod test {
             assert_eq!(result.time.hour(), 13);
         }
     }
+
+    #[test]
+    fn weeks_span_year_boundaries() {
+        use time::macros::date;
+
+        // Mon 2024-12-30 to Fri 2025-01-03 is one trading week
+        for day in [date!(2024 - 12 - 30), date!(2025 - 01 - 03)] {
+            assert_eq!(get_week_of_year(&day, WeekStart::Sunday), (2025, 1));
+            assert_eq!(get_week_of_year(&day, WeekStart::Monday), (2025, 1));
+            assert_eq!(get_week_of_year(&day, WeekStart::Iso), (2025, 1));
+        }
+        assert_eq!(
+            get_start_of_week(&date!(2025 - 01 - 03), WeekStart::Sunday),
+            date!(2024 - 12 - 29)
+        );
+        assert_eq!(
+            get_start_of_week(&date!(2025 - 01 - 03), WeekStart::Monday),
+            date!(2024 - 12 - 30)
+        );
+
+        // ISO puts Jan 1 in the last week of the previous year when it falls on Fri-Sun
+        let day = date!(2027 - 01 - 01);
+        assert_eq!(get_week_of_year(&day, WeekStart::Iso), (2026, 53));
+        assert_eq!(get_week_of_year(&day, WeekStart::Monday), (2027, 1));
+        assert_eq!(
+            get_week_of_year(&date!(2026 - 12 - 27), WeekStart::Sunday),
+            (2027, 1)
+        );
+        assert_eq!(
+            get_week_of_year(&date!(2026 - 12 - 26), WeekStart::Sunday),
+            (2026, 52)
+        );
+    }
+
+    #[test]
+    fn period_keys_and_starts() {
+        use time::macros::date;
+
+        let day = date!(2024 - 08 - 14);
+        assert_eq!(Period::Month.key(&day), (2024, 8));
+        assert_eq!(Period::Quarter.key(&day), (2024, 3));
+        assert_eq!(Period::Year.key(&day), (2024, 0));
+        assert_eq!(Period::Month.start(&day).unwrap(), date!(2024 - 08 - 01));
+        assert_eq!(Period::Quarter.start(&day).unwrap(), date!(2024 - 07 - 01));
+        assert_eq!(Period::Year.start(&day).unwrap(), date!(2024 - 01 - 01));
+        assert_eq!("quarterly".parse::<Period>().unwrap(), Period::Quarter);
+        assert!("daily".parse::<Period>().is_err());
+    }
+
+    #[test]
+    fn chunks_cover_range() {
+        use time::macros::date;
+
+        let chunks = date_chunks(date!(2024 - 01 - 01), date!(2024 - 01 - 10), 4);
+        assert_eq!(
+            chunks,
+            [
+                (date!(2024 - 01 - 01), date!(2024 - 01 - 04)),
+                (date!(2024 - 01 - 05), date!(2024 - 01 - 08)),
+                (date!(2024 - 01 - 09), date!(2024 - 01 - 10)),
+            ]
+        );
+        assert_eq!(
+            date_chunks(date!(2024 - 01 - 02), date!(2024 - 01 - 01), 4),
+            []
+        );
+        assert_eq!(
+            date_chunks(date!(2024 - 01 - 01), date!(2024 - 01 - 01), 0).len(),
+            1
+        );
+    }
 }
@@ -1,6 +1,10 @@
-use super::{settings, Result};
+use super::{Result, error::Error, settings};
 use deadpool_postgres::Pool;
-use tokio_postgres::{types::ToSql, Row};
+use tokio_postgres::{Row, Transaction, binary_copy::BinaryCopyInWriter, types::ToSql};
+use tracing::{Level, event};
+
+/// Values of one row for `copy_in`, in the order of its columns
+pub type CopyRow<'a> = Vec<&'a (dyn ToSql + Sync)>;
 
 #[allow(unused)]
 static POOL: std::sync::OnceLock<Pool> = std::sync::OnceLock::new();
@@ -27,6 +31,446 @@ pub async fn query(sql: &str, params: &[&(dyn ToSql + Sync)]) -> Result<Vec<Row>
     Ok(rows)
 }
 
+/// Binary COPY `rows` of `columns` into a temporary staging table shaped like `table`
+///
+/// Returns the name of the staging table to merge from; it is emptied on every call and dropped at commit
+#[tracing::instrument(skip(transaction, rows), err)]
+pub async fn copy_in(
+    transaction: &Transaction<'_>,
+    table: &str,
+    columns: &[&str],
+    rows: &[CopyRow<'_>],
+) -> Result<String> {
+    let staging = format!("staging_{}", table);
+
+    transaction
+        .batch_execute(&format!(
+            "CREATE TEMP TABLE IF NOT EXISTS {staging} ON COMMIT DROP AS
+                SELECT {columns} FROM {table} WITH NO DATA;
+            TRUNCATE {staging};",
+            columns = columns.join(","),
+        ))
+        .await?;
+    copy_into(transaction, &staging, columns, rows).await?;
+
+    Ok(staging)
+}
+
+/// Binary COPY `rows` of `columns` into `table` as they are
+async fn copy_into(
+    transaction: &Transaction<'_>,
+    table: &str,
+    columns: &[&str],
+    rows: &[CopyRow<'_>],
+) -> Result<()> {
+    let columns = columns.join(",");
+
+    // Binary format needs the exact column types
+    let statement = transaction
+        .prepare(&format!("SELECT {columns} FROM {table};"))
+        .await?;
+    let types: Vec<_> = statement
+        .columns()
+        .iter()
+        .map(|column| column.type_().clone())
+        .collect();
+
+    let sink = transaction
+        .copy_in(&format!("COPY {table}({columns}) FROM STDIN BINARY;"))
+        .await?;
+    let mut writer = std::pin::pin!(BinaryCopyInWriter::new(sink, &types));
+    for row in rows {
+        writer.as_mut().write(row).await?;
+    }
+    writer.finish().await?;
+
+    Ok(())
+}
+
+/// What a new version of a table must pass before it replaces the live one
+#[derive(Debug)]
+pub struct Check<'a> {
+    pub min_rows: u64,
+    pub required: &'a [&'a str], // columns that must not be null or blank
+}
+
+/// Replace `table` with `rows` and return the number of rows
+///
+/// Rows are loaded into `{table}_next` and checked before it is renamed over the live table,
+/// so readers are blocked only for the rename. The replaced version is kept as `{table}_previous`
+/// until the next swap; see `swap_back`. `table` must have an `id` column filled by the DB.
+#[tracing::instrument(skip(rows), err)]
+pub async fn swap_in(
+    table: &str,
+    columns: &[&str],
+    rows: &[CopyRow<'_>],
+    check: &Check<'_>,
+) -> Result<u64> {
+    let mut client = pool().get().await?;
+    let transaction = client.transaction().await?;
+    let total = load_and_promote(&transaction, table, columns, rows, check).await?;
+    transaction.commit().await?;
+
+    Ok(total)
+}
+
+async fn load_and_promote(
+    transaction: &Transaction<'_>,
+    table: &str,
+    columns: &[&str],
+    rows: &[CopyRow<'_>],
+    check: &Check<'_>,
+) -> Result<u64> {
+    let next = format!("{}_next", table);
+
+    create_next(transaction, table, &next).await?;
+    copy_into(transaction, &next, columns, rows).await?;
+
+    let total = verify(transaction, table, &next, check).await?;
+
+    promote(transaction, table).await?;
+    Ok(total)
+}
+
+/// Put the version of `table` replaced by the last `swap_in` back; the current one becomes the previous
+#[tracing::instrument(err)]
+pub async fn swap_back(table: &str) -> Result<()> {
+    let mut client = pool().get().await?;
+    let transaction = client.transaction().await?;
+    restore_previous(&transaction, table).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn restore_previous(transaction: &Transaction<'_>, table: &str) -> Result<()> {
+    let previous = format!("{}_previous", table);
+    let current = format!("{}_rollback", table);
+
+    let row = transaction
+        .query_one(
+            "SELECT to_regclass($1::TEXT) IS NOT NULL AS exists;",
+            &[&previous],
+        )
+        .await?;
+    if !row.get::<_, bool>("exists") {
+        return Err(Error::E404NotFound(format!(
+            "previous version of {}",
+            table
+        )));
+    }
+
+    swap_names(transaction, table, &previous).await?;
+    transaction
+        .batch_execute(&format!(
+            "ALTER TABLE {table} RENAME TO {current};
+            ALTER TABLE {previous} RENAME TO {table};
+            ALTER TABLE {current} RENAME TO {previous};"
+        ))
+        .await?;
+    Ok(())
+}
+
+/// Create `next` empty with the columns, defaults, constraints and indexes of `table`
+///
+/// It gets its own id sequence so that dropping `table` later keeps it. `{table}_previous` is
+/// dropped first, which frees the generated names it took from the last `next`; it is replaced
+/// in the same transaction anyway.
+async fn create_next(transaction: &Transaction<'_>, table: &str, next: &str) -> Result<()> {
+    transaction
+        .batch_execute(&format!(
+            "DROP TABLE IF EXISTS {next}, {table}_previous;
+            CREATE TABLE {next} (LIKE {table} INCLUDING ALL EXCLUDING IDENTITY);
+            ALTER TABLE {next} ALTER COLUMN id DROP DEFAULT;
+            ALTER TABLE {next} ALTER COLUMN id ADD GENERATED BY DEFAULT AS IDENTITY;"
+        ))
+        .await?;
+    Ok(())
+}
+
+/// Rename `{table}_next` from `create_next` over `table`, which is kept as `{table}_previous`
+async fn promote(transaction: &Transaction<'_>, table: &str) -> Result<()> {
+    let next = format!("{}_next", table);
+    let previous = format!("{}_previous", table);
+
+    swap_names(transaction, table, &next).await?;
+    transaction
+        .batch_execute(&format!(
+            "ALTER TABLE {table} RENAME TO {previous};
+            ALTER TABLE {next} RENAME TO {table};"
+        ))
+        .await?;
+    Ok(())
+}
+
+/// Swap the names of the matching indexes and id sequences of two versions of a table
+///
+/// Index names are unique per schema, so a copy made with `LIKE` gets generated names such as
+/// `dart_code_next_pkey`; swapping them keeps the live table's names from drifting on every swap.
+/// Constraints follow the indexes behind them.
+async fn swap_names(transaction: &Transaction<'_>, a: &str, b: &str) -> Result<()> {
+    // Indexes are matched by what they cover, which does not depend on their table or name
+    const SQL_INDEXES: &str = "
+        SELECT c.relname AS name,
+            i.indisprimary::TEXT || i.indisunique::TEXT
+                || regexp_replace(pg_get_indexdef(i.indexrelid), '^.* USING ', '') AS signature
+        FROM pg_index i JOIN pg_class c ON c.oid = i.indexrelid
+        WHERE i.indrelid = $1::TEXT::REGCLASS
+        ORDER BY signature, name;";
+    const SQL_SEQUENCE: &str = "
+        SELECT c.relname AS name FROM pg_class c
+        WHERE c.oid = pg_get_serial_sequence($1::TEXT, 'id')::REGCLASS;";
+
+    let mut pairs: Vec<(String, String, &str)> = Vec::new();
+
+    let b_indexes = transaction.query(SQL_INDEXES, &[&b]).await?;
+    let mut b_indexes: Vec<(String, String)> = b_indexes
+        .iter()
+        .map(|row| (row.get("signature"), row.get("name")))
+        .collect();
+    for row in transaction.query(SQL_INDEXES, &[&a]).await? {
+        let signature: String = row.get("signature");
+        if let Some(i) = b_indexes.iter().position(|(s, _)| *s == signature) {
+            let (_, b_name) = b_indexes.remove(i);
+            pairs.push((row.get("name"), b_name, "INDEX"));
+        }
+    }
+
+    let a_sequence = transaction.query_opt(SQL_SEQUENCE, &[&a]).await?;
+    let b_sequence = transaction.query_opt(SQL_SEQUENCE, &[&b]).await?;
+    if let (Some(a_sequence), Some(b_sequence)) = (a_sequence, b_sequence) {
+        pairs.push((a_sequence.get("name"), b_sequence.get("name"), "SEQUENCE"));
+    }
+
+    let mut sql = String::new();
+    for (a_name, _, kind) in &pairs {
+        sql += &format!("ALTER {kind} {a_name} RENAME TO {a_name}_swap;");
+    }
+    for (a_name, b_name, kind) in &pairs {
+        sql += &format!("ALTER {kind} {b_name} RENAME TO {a_name};");
+        sql += &format!("ALTER {kind} {a_name}_swap RENAME TO {b_name};");
+    }
+    transaction.batch_execute(&sql).await?;
+
+    Ok(())
+}
+
+/// Check `loaded` against `check` and return its number of rows; `table` names it in errors
+async fn verify(
+    transaction: &Transaction<'_>,
+    table: &str,
+    loaded: &str,
+    check: &Check<'_>,
+) -> Result<u64> {
+    let blank = match check.required {
+        [] => "FALSE".to_string(),
+        required => required
+            .iter()
+            .map(|c| format!("COALESCE(TRIM({c}::TEXT),'')=''"))
+            .collect::<Vec<_>>()
+            .join(" OR "),
+    };
+    let row = transaction
+        .query_one(
+            &format!(
+                "SELECT COUNT(*) AS total, COUNT(*) FILTER (WHERE {blank}) AS blank FROM {loaded};"
+            ),
+            &[],
+        )
+        .await?;
+    let total = row.get::<_, i64>("total") as u64;
+    let blank = row.get::<_, i64>("blank");
+
+    // Nothing is kept when the check fails as the transaction is rolled back
+    if total < check.min_rows {
+        return Err(Error::General(format!(
+            "{} has {} rows while at least {} are expected; kept the current version",
+            table, total, check.min_rows
+        )));
+    }
+    if blank > 0 {
+        return Err(Error::General(format!(
+            "{} has {} rows with blank {}; kept the current version",
+            table,
+            blank,
+            check.required.join(" or ")
+        )));
+    }
+
+    Ok(total)
+}
+
+/// Keys whose current version was replaced by `merge_history`
+#[derive(Debug, Default)]
+pub struct Merged {
+    pub rows: u64,
+    pub listed: u64,   // new keys
+    pub changed: u64,  // keys with different values
+    pub delisted: u64, // keys missing from the rows
+}
+
+/// Bring the versions in `table` up to date with `rows` as of `date`
+///
+/// Each row holds `key` followed by `values`. The current version of a key has a null `valid_to`;
+/// it is closed on `date` when the key is missing from `rows` or its values differ, and a version
+/// valid from `date` is added for new and changed keys. Only the first row of a key is used.
+///
+/// Rows are checked as in `swap_in` first. The merge is applied to a copy of `table` that is then
+/// swapped in, so the version before it is kept for `swap_back`.
+#[tracing::instrument(skip(rows), err)]
+pub async fn merge_history(
+    table: &str,
+    key: &str,
+    values: &[&str],
+    rows: &[CopyRow<'_>],
+    check: &Check<'_>,
+    date: time::Date,
+) -> Result<Merged> {
+    let columns = [&[key], values].concat();
+    let same = format!(
+        "s.{key}=t.{key} AND ({}) IS NOT DISTINCT FROM ({})",
+        values
+            .iter()
+            .map(|c| format!("s.{c}"))
+            .collect::<Vec<_>>()
+            .join(","),
+        values
+            .iter()
+            .map(|c| format!("t.{c}"))
+            .collect::<Vec<_>>()
+            .join(","),
+    );
+
+    let mut client = pool().get().await?;
+    let transaction = client.transaction().await?;
+
+    let staging = copy_in(&transaction, table, &columns, rows).await?;
+    // A key twice in the source would break the unique index on current versions
+    let duplicates = transaction
+        .execute(
+            &format!(
+                "DELETE FROM {staging} a USING {staging} b WHERE a.{key}=b.{key} AND a.ctid > b.ctid;"
+            ),
+            &[],
+        )
+        .await?;
+    if duplicates > 0 {
+        event!(
+            Level::WARN,
+            "ignored {} duplicate rows of {}",
+            duplicates,
+            table
+        );
+    }
+    let total = verify(&transaction, table, &staging, check).await?;
+
+    // Versions never end before they start
+    let row = transaction
+        .query_one(
+            &format!("SELECT MAX(valid_from) AS latest FROM {table};"),
+            &[],
+        )
+        .await?;
+    if let Some(latest) = row.get::<_, Option<time::Date>>("latest")
+        && latest > date
+    {
+        return Err(Error::General(format!(
+            "{} already has versions from {}; kept the current version",
+            table, latest
+        )));
+    }
+
+    let next = format!("{}_next", table);
+    create_next(&transaction, table, &next).await?;
+    transaction
+        .batch_execute(&format!(
+            "INSERT INTO {next} SELECT * FROM {table};
+            SELECT setval(pg_get_serial_sequence('{next}', 'id'), COALESCE(MAX(id), 0) + 1, false)
+                FROM {next};"
+        ))
+        .await?;
+
+    // A version from the same date that no longer matches is replaced rather than closed
+    let replaced = transaction
+        .query(
+            &format!(
+                "DELETE FROM {next} t WHERE t.valid_to IS NULL AND t.valid_from=$1::DATE
+                    AND NOT EXISTS (SELECT 1 FROM {staging} s WHERE {same})
+                RETURNING t.{key}::TEXT AS key;"
+            ),
+            &[&date],
+        )
+        .await?;
+    let closed = transaction
+        .query(
+            &format!(
+                "UPDATE {next} t SET valid_to=$1::DATE WHERE t.valid_to IS NULL
+                    AND NOT EXISTS (SELECT 1 FROM {staging} s WHERE {same})
+                RETURNING t.{key}::TEXT AS key;"
+            ),
+            &[&date],
+        )
+        .await?;
+    let closed: std::collections::HashSet<String> = replaced
+        .iter()
+        .chain(&closed)
+        .map(|row| row.get("key"))
+        .collect();
+    let opened: std::collections::HashSet<String> = transaction
+        .query(
+            &format!(
+                "INSERT INTO {next}({columns},valid_from) SELECT {columns},$1::DATE FROM {staging} s
+                    WHERE NOT EXISTS (
+                        SELECT 1 FROM {next} t WHERE t.valid_to IS NULL AND t.{key}=s.{key}
+                    )
+                RETURNING {key}::TEXT AS key;",
+                columns = columns.join(","),
+            ),
+            &[&date],
+        )
+        .await?
+        .iter()
+        .map(|row| row.get("key"))
+        .collect();
+
+    promote(&transaction, table).await?;
+    transaction.commit().await?;
+
+    let changed = closed.intersection(&opened).count() as u64;
+    Ok(Merged {
+        rows: total,
+        listed: opened.len() as u64 - changed,
+        changed,
+        delisted: closed.len() as u64 - changed,
+    })
+}
+
+/// Merge `staging` into `table`, updating on conflict of `key` only the rows whose `values` differ
+pub fn upsert_sql(table: &str, staging: &str, key: &[&str], values: &[&str]) -> String {
+    let list = |prefix: &str| {
+        values
+            .iter()
+            .map(|c| format!("{prefix}{c}"))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let columns = [key, values].concat().join(",");
+    let set = values
+        .iter()
+        .map(|c| format!("{c}=EXCLUDED.{c}"))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    format!(
+        "INSERT INTO {table} AS t({columns}) SELECT {columns} FROM {staging}
+        ON CONFLICT ({key}) DO UPDATE SET {set}
+        WHERE ({current}) IS DISTINCT FROM ({excluded});",
+        key = key.join(","),
+        current = list("t."),
+        excluded = list("EXCLUDED."),
+    )
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -36,4 +480,152 @@ mod test {
         let client = pool().get().await.unwrap();
         assert!(!client.is_closed())
     }
+
+    /// Compare row-by-row inserts with `copy_in` and a merge; run with `cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn bench_copy_in() {
+        const N: usize = 20_000;
+        const SQL_INSERT: &str = "
+            INSERT INTO price(bas_dt,srtn_cd,clpr) VALUES ($1::DATE,$2::CHAR(6),$3::INTEGER)
+            ON CONFLICT (bas_dt,srtn_cd) DO NOTHING;";
+
+        let start = time::macros::date!(1900 - 01 - 01);
+        let dates: Vec<time::Date> = (0..N)
+            .map(|i| start + time::Duration::days(i as i64))
+            .collect();
+        let close = 1000;
+
+        // Rolled back when dropped
+        let mut client = pool().get().await.unwrap();
+
+        let transaction = client.transaction().await.unwrap();
+        let timer = std::time::Instant::now();
+        let statement = transaction.prepare(SQL_INSERT).await.unwrap();
+        for date in &dates {
+            transaction
+                .execute(&statement, &[date, &"BENCH0", &close])
+                .await
+                .unwrap();
+        }
+        let row_by_row = timer.elapsed();
+        drop(transaction);
+
+        let transaction = client.transaction().await.unwrap();
+        let timer = std::time::Instant::now();
+        let columns = ["bas_dt", "srtn_cd", "clpr"];
+        let rows: Vec<CopyRow> = dates
+            .iter()
+            .map(|date| -> CopyRow { vec![date, &"BENCH0", &close] })
+            .collect();
+        let staging = copy_in(&transaction, "price", &columns, &rows)
+            .await
+            .unwrap();
+        let n = transaction
+            .execute(
+                &format!(
+                    "INSERT INTO price(bas_dt,srtn_cd,clpr) SELECT bas_dt,srtn_cd,clpr FROM {staging}
+                    ON CONFLICT (bas_dt,srtn_cd) DO NOTHING;"
+                ),
+                &[],
+            )
+            .await
+            .unwrap();
+        let copied = timer.elapsed();
+        drop(transaction);
+
+        assert_eq!(n as usize, N);
+        event!(
+            Level::INFO,
+            "{} rows: row by row {:?}, copy and merge {:?} ({:.1}x)",
+            N,
+            row_by_row,
+            copied,
+            row_by_row.as_secs_f64() / copied.as_secs_f64()
+        );
+    }
+
+    #[tokio::test]
+    async fn upsert_skips_unchanged_rows() {
+        const SQL_SETUP: &str = "
+            CREATE TEMP TABLE upsert_target (k INTEGER PRIMARY KEY, a INTEGER, b TEXT);
+            CREATE TEMP TABLE upsert_staging (k INTEGER, a INTEGER, b TEXT);
+            INSERT INTO upsert_target VALUES (1, 10, 'x'), (2, 20, 'y'), (3, 30, NULL);
+            INSERT INTO upsert_staging VALUES (1, 10, 'x'), (2, 21, 'y'), (3, 30, NULL), (4, 40, 'z');";
+
+        // Rolled back when dropped
+        let mut client = pool().get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        transaction.batch_execute(SQL_SETUP).await.unwrap();
+
+        // Row 2 is updated and row 4 inserted; unchanged rows, NULLs included, are not written
+        let sql = upsert_sql("upsert_target", "upsert_staging", &["k"], &["a", "b"]);
+        let n = transaction.execute(&sql, &[]).await.unwrap();
+        assert_eq!(n, 2);
+
+        let rows = transaction
+            .query("SELECT k, a FROM upsert_target ORDER BY k;", &[])
+            .await
+            .unwrap();
+        let values: Vec<(i32, i32)> = rows.iter().map(|r| (r.get(0), r.get(1))).collect();
+        assert_eq!(values, [(1, 10), (2, 21), (3, 30), (4, 40)]);
+
+        // Nothing differs the second time
+        assert_eq!(transaction.execute(&sql, &[]).await.unwrap(), 0);
+    }
+
+    /// Creates and renames tables; run with `cargo test -- --ignored`
+    #[tokio::test]
+    #[ignore]
+    async fn swap_keeps_names() {
+        const SQL_NAMES: &str = "
+            SELECT c.relname AS name FROM pg_class c
+            WHERE c.relname LIKE 'swap_test%' AND c.relkind IN ('i', 'S')
+            ORDER BY name;";
+
+        // Rolled back when dropped
+        let mut client = pool().get().await.unwrap();
+        let transaction = client.transaction().await.unwrap();
+        transaction
+            .batch_execute(
+                "CREATE TABLE swap_test (id SERIAL PRIMARY KEY, code TEXT UNIQUE);
+                CREATE INDEX swap_test_code_lower ON swap_test(LOWER(code));",
+            )
+            .await
+            .unwrap();
+        let names = || async {
+            let rows = transaction.query(SQL_NAMES, &[]).await.unwrap();
+            rows.iter()
+                .map(|row| row.get::<_, String>("name"))
+                .collect::<Vec<_>>()
+        };
+        let before = names().await;
+
+        let check = Check {
+            min_rows: 1,
+            required: &["code"],
+        };
+        let mut swapped = Vec::new();
+        for code in ["a", "b", "c"] {
+            let rows: Vec<CopyRow> = vec![vec![&code]];
+            load_and_promote(&transaction, "swap_test", &["code"], &rows, &check)
+                .await
+                .unwrap();
+            swapped.push(names().await);
+        }
+        restore_previous(&transaction, "swap_test").await.unwrap();
+        let restored = names().await;
+
+        let rows = transaction
+            .query("SELECT code FROM swap_test;", &[])
+            .await
+            .unwrap();
+
+        // The live table keeps its names; the previous one always takes those of `_next`
+        assert!(before.iter().all(|n| swapped[0].contains(n)));
+        assert_eq!(swapped[0].len(), before.len() * 2);
+        assert!(swapped.iter().all(|names| *names == swapped[0]));
+        assert_eq!(restored, swapped[0]);
+        assert_eq!(rows[0].get::<_, String>("code"), "b");
+    }
 }
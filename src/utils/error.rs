@@ -32,22 +32,41 @@ pub enum Error {
     Zip(zip::result::ZipError),
     XmltreeParse(roxmltree::Error),
     Decimal(rust_decimal::Error),
+    Prometheus(prometheus::Error),
 
     // HTTP Error responses
     E400BadRequest(String),
-    #[allow(unused)]
     E401Unauthorized(String),
-    #[allow(unused)]
     E403Forbidden(String),
     E404NotFound(String),
     #[allow(unused)]
     E409Conflict(String),
     #[allow(unused)]
     E410Gone(String),
+    E429TooManyRequests(String, u64), // message, seconds to retry after
     #[allow(unused)]
     E500(String),
 }
 
+impl Error {
+    /// Copy of the error for another caller; HTTP errors keep their variant, the rest become `General`
+    pub fn share(&self) -> Self {
+        use Error::*;
+        match self {
+            E400BadRequest(s) => E400BadRequest(s.clone()),
+            E401Unauthorized(s) => E401Unauthorized(s.clone()),
+            E403Forbidden(s) => E403Forbidden(s.clone()),
+            E404NotFound(s) => E404NotFound(s.clone()),
+            E409Conflict(s) => E409Conflict(s.clone()),
+            E410Gone(s) => E410Gone(s.clone()),
+            E429TooManyRequests(s, secs) => E429TooManyRequests(s.clone(), *secs),
+            E500(s) => E500(s.clone()),
+            General(s) => General(s.clone()),
+            e => General(e.to_string()),
+        }
+    }
+}
+
 impl std::error::Error for Error {}
 unsafe impl Send for Error {}
 unsafe impl Sync for Error {}
@@ -81,6 +100,7 @@ impl std::fmt::Display for Error {
             Zip(e) => e.fmt(f),
             XmltreeParse(e) => e.fmt(f),
             Decimal(e) => e.fmt(f),
+            Prometheus(e) => e.fmt(f),
 
             E400BadRequest(s) => write!(f, "BAD_REQUEST {}", s),
             E401Unauthorized(s) => write!(f, "UNAUTHORIZED {}", s),
@@ -88,6 +108,7 @@ impl std::fmt::Display for Error {
             E404NotFound(s) => write!(f, "NOT_FOUND {}", s),
             E409Conflict(s) => write!(f, "CONFLICT {}", s),
             E410Gone(s) => write!(f, "GONE {}", s),
+            E429TooManyRequests(s, _) => write!(f, "TOO_MANY_REQUESTS {}", s),
             E500(s) => write!(f, "INTERNAL_SERVER_ERROR {}", s),
         }
     }
@@ -103,6 +124,7 @@ impl actix_web::error::ResponseError for Error {
             E404NotFound(_) => StatusCode::NOT_FOUND,
             E409Conflict(_) => StatusCode::CONFLICT,
             E410Gone(_) => StatusCode::GONE,
+            E429TooManyRequests(_, _) => StatusCode::TOO_MANY_REQUESTS,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -126,6 +148,13 @@ impl actix_web::error::ResponseError for Error {
                     r#"Bearer realm="medicord", error="invalid_token""#,
                 ))
                 .body(body),
+            Error::E429TooManyRequests(_, retry_after) => actix_web::HttpResponse::build(code)
+                .insert_header(actix_web::http::header::ContentType::json())
+                .insert_header((
+                    actix_web::http::header::RETRY_AFTER,
+                    retry_after.to_string(),
+                ))
+                .body(body),
             _ => actix_web::HttpResponse::build(code)
                 .insert_header(actix_web::http::header::ContentType::json())
                 .body(body),
@@ -276,3 +305,9 @@ impl From<rust_decimal::Error> for Error {
         Self::Decimal(err)
     }
 }
+
+impl From<prometheus::Error> for Error {
+    fn from(err: prometheus::Error) -> Self {
+        Self::Prometheus(err)
+    }
+}
@@ -0,0 +1,192 @@
+use super::{Result, db};
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+};
+
+/// Upstream services this backend proxies
+#[derive(Debug, Clone, Copy)]
+pub enum Upstream {
+    DataGoKr,
+    Krx,
+    Yahoo,
+    Sec,
+    OpenDart,
+}
+
+impl Upstream {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::DataGoKr => "data_go_kr",
+            Self::Krx => "krx",
+            Self::Yahoo => "yahoo",
+            Self::Sec => "sec",
+            Self::OpenDart => "opendart",
+        }
+    }
+}
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    upstream_requests: IntCounterVec,
+    upstream_duration: HistogramVec,
+    cache_requests: IntCounterVec,
+    db_pool: IntGaugeVec,
+}
+
+static METRICS: std::sync::OnceLock<Metrics> = std::sync::OnceLock::new();
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency"),
+            &["method", "route"],
+        )
+        .unwrap();
+        let upstream_requests = IntCounterVec::new(
+            Opts::new("upstream_requests_total", "Requests to upstream providers"),
+            &["provider", "outcome"],
+        )
+        .unwrap();
+        let upstream_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "upstream_request_duration_seconds",
+                "Latency of upstream providers",
+            ),
+            &["provider"],
+        )
+        .unwrap();
+        let cache_requests = IntCounterVec::new(
+            Opts::new("cache_requests_total", "Redis cache lookups by result"),
+            &["result"],
+        )
+        .unwrap();
+        let db_pool = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Postgres connection pool status"),
+            &["state"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry.register(Box::new(http_duration.clone())).unwrap();
+        registry
+            .register(Box::new(upstream_requests.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(upstream_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(cache_requests.clone())).unwrap();
+        registry.register(Box::new(db_pool.clone())).unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            http_duration,
+            upstream_requests,
+            upstream_duration,
+            cache_requests,
+            db_pool,
+        }
+    }
+
+    pub fn instance() -> &'static Self {
+        METRICS.get_or_init(Self::new)
+    }
+}
+
+/// Middleware: count requests and measure latency per route pattern
+pub async fn track(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> std::result::Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let metrics = Metrics::instance();
+    let method = req.method().to_string();
+    let start = std::time::Instant::now();
+
+    let res = next.call(req).await;
+
+    // Use the pattern, e.g. `/v1/prices/{short_code}/daily`, to keep label cardinality bounded
+    let (route, status) = match &res {
+        Ok(r) => (
+            r.request()
+                .match_pattern()
+                .unwrap_or_else(|| "unmatched".into()),
+            r.status(),
+        ),
+        Err(e) => ("unmatched".into(), e.as_response_error().status_code()),
+    };
+    metrics
+        .http_requests
+        .with_label_values(&[method.as_str(), route.as_str(), status.as_str()])
+        .inc();
+    metrics
+        .http_duration
+        .with_label_values(&[method.as_str(), route.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+
+    res
+}
+
+/// Send a request to an upstream provider, recording its latency and outcome
+pub async fn send(upstream: Upstream, req: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+    let metrics = Metrics::instance();
+    let start = std::time::Instant::now();
+
+    let res = req.send().await.and_then(|r| r.error_for_status());
+
+    let outcome = if res.is_ok() { "ok" } else { "error" };
+    metrics
+        .upstream_requests
+        .with_label_values(&[upstream.as_str(), outcome])
+        .inc();
+    metrics
+        .upstream_duration
+        .with_label_values(&[upstream.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+
+    Ok(res?)
+}
+
+/// Count a cache lookup or failure: `hit`, `miss`, `fallback_hit`, `decode_error` or `error`
+pub fn count_cache(result: &str) {
+    Metrics::instance()
+        .cache_requests
+        .with_label_values(&[result])
+        .inc();
+}
+
+/// Render all metrics in the Prometheus text format
+pub fn gather() -> Result<String> {
+    let metrics = Metrics::instance();
+
+    let status = db::pool().status();
+    let gauges = [
+        ("max", status.max_size),
+        ("size", status.size),
+        ("available", status.available),
+        ("waiting", status.waiting),
+    ];
+    for (state, n) in gauges {
+        metrics
+            .db_pool
+            .with_label_values(&[state])
+            .set(i64::try_from(n)?);
+    }
+
+    let mut buf = Vec::new();
+    prometheus::TextEncoder::new().encode(&metrics.registry.gather(), &mut buf)?;
+    Ok(String::from_utf8(buf)?)
+}
@@ -0,0 +1,202 @@
+use super::{Result, db, error::Error};
+use tracing::{Level, event};
+
+/// One step of the schema; never edit a migration once released, add another instead
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub sql: &'static str,
+    // Off for `CREATE INDEX CONCURRENTLY`; it then runs one statement at a time and must be rerunnable
+    pub transaction: bool,
+}
+
+/// Every migration in order of version
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "init",
+        sql: include_str!("../../migrations/0001_init.sql"),
+        transaction: true,
+    },
+    Migration {
+        version: 2,
+        name: "indexes",
+        sql: include_str!("../../migrations/0002_indexes.sql"),
+        transaction: false,
+    },
+    Migration {
+        version: 3,
+        name: "job_heartbeat",
+        sql: include_str!("../../migrations/0003_job_heartbeat.sql"),
+        transaction: true,
+    },
+    Migration {
+        version: 4,
+        name: "rebuild_weekly",
+        sql: include_str!("../../migrations/0004_rebuild_weekly.sql"),
+        transaction: true,
+    },
+    Migration {
+        version: 5,
+        name: "dedupe_tickers",
+        sql: include_str!("../../migrations/0005_dedupe_tickers.sql"),
+        transaction: true,
+    },
+    Migration {
+        version: 6,
+        name: "clear_weekly",
+        sql: include_str!("../../migrations/0006_clear_weekly.sql"),
+        transaction: true,
+    },
+];
+
+/// Apply the migrations not yet recorded in `schema_migrations` and return their versions
+///
+/// Each migration is applied and recorded in its own transaction, or statement by statement if it
+/// cannot run in one, under a lock so that instances starting together take turns; a failing
+/// migration stops the run and is tried again next time.
+#[tracing::instrument(err)]
+pub async fn run() -> Result<Vec<i32>> {
+    const SQL_LOCK: &str = "SELECT pg_try_advisory_lock(hashtext('schema_migrations'));";
+    const SQL_UNLOCK: &str = "SELECT pg_advisory_unlock(hashtext('schema_migrations'));";
+
+    let mut client = db::pool().get().await?;
+
+    // Retried rather than waited on: `CREATE INDEX CONCURRENTLY` waits for every open snapshot,
+    // including that of a blocked lock call, so a waiting instance would deadlock with the holder
+    while !client.query_one(SQL_LOCK, &[]).await?.get::<_, bool>(0) {
+        event!(Level::INFO, "waiting for another instance to migrate");
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+
+    // Held by the session rather than a transaction, so it must be released on every path
+    let res = apply_pending(&mut client).await;
+    client.batch_execute(SQL_UNLOCK).await?;
+
+    res
+}
+
+async fn apply_pending(client: &mut deadpool_postgres::Object) -> Result<Vec<i32>> {
+    const SQL_TABLE: &str = "
+        CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT,
+            applied_at TIMESTAMPTZ DEFAULT NOW()
+        );";
+    const SQL_APPLIED: &str = "SELECT version FROM schema_migrations;";
+    const SQL_RECORD: &str =
+        "INSERT INTO schema_migrations(version,name) VALUES ($1::INTEGER,$2::TEXT);";
+
+    client.batch_execute(SQL_TABLE).await?;
+    let applied: Vec<i32> = client
+        .query(SQL_APPLIED, &[])
+        .await?
+        .iter()
+        .map(|row| row.get("version"))
+        .collect();
+
+    if let Some(unknown) = applied
+        .iter()
+        .find(|v| !MIGRATIONS.iter().any(|m| m.version == **v))
+    {
+        event!(
+            Level::WARN,
+            version = unknown,
+            "database has a migration unknown to this build"
+        );
+    }
+
+    let mut versions = Vec::new();
+    for migration in pending(&applied) {
+        event!(
+            Level::INFO,
+            migration.version,
+            migration.name,
+            "applying migration"
+        );
+        let failed = |e: tokio_postgres::Error| {
+            Error::General(format!(
+                "migration {} {} failed: {}",
+                migration.version, migration.name, e
+            ))
+        };
+
+        if migration.transaction {
+            let transaction = client.transaction().await?;
+            transaction
+                .batch_execute(migration.sql)
+                .await
+                .map_err(failed)?;
+            transaction
+                .execute(SQL_RECORD, &[&migration.version, &migration.name])
+                .await?;
+            transaction.commit().await?;
+        } else {
+            // Several statements in one query would run in an implicit transaction
+            for statement in statements(migration.sql) {
+                client.batch_execute(&statement).await.map_err(failed)?;
+            }
+            client
+                .execute(SQL_RECORD, &[&migration.version, &migration.name])
+                .await?;
+        }
+        versions.push(migration.version);
+    }
+
+    Ok(versions)
+}
+
+/// Statements of a migration without comments; `;` may only appear at the end of a statement
+fn statements(sql: &str) -> Vec<String> {
+    let sql = sql
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    sql.split(';')
+        .map(str::trim)
+        .filter(|statement| !statement.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Migrations not in `applied`, in order of version
+fn pending(applied: &[i32]) -> impl Iterator<Item = &'static Migration> + '_ {
+    MIGRATIONS
+        .iter()
+        .filter(move |migration| !applied.contains(&migration.version))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn versions_increase() {
+        assert!(!MIGRATIONS.is_empty());
+        assert_eq!(MIGRATIONS[0].version, 1);
+        assert!(
+            MIGRATIONS
+                .windows(2)
+                .all(|w| w[1].version == w[0].version + 1)
+        );
+        assert!(MIGRATIONS.iter().all(|m| !m.sql.trim().is_empty()));
+    }
+
+    #[test]
+    fn skips_applied() {
+        let versions: Vec<_> = pending(&[1]).map(|m| m.version).collect();
+        assert_eq!(versions, [2, 3, 4, 5, 6]);
+        assert_eq!(pending(&[]).count(), MIGRATIONS.len());
+    }
+
+    #[test]
+    fn splits_statements() {
+        let sql = "-- Comment\nDROP INDEX x;\n\n-- Another; comment\nCREATE INDEX x\n  ON t(a);\n";
+        assert_eq!(
+            statements(sql),
+            ["DROP INDEX x", "CREATE INDEX x\n  ON t(a)"]
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use super::{Result, datetime::date_opt_deserialize, error::Error};
+
+pub const DEFAULT_LIMIT: i64 = 400;
+pub const MAX_LIMIT: i64 = 5000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl Order {
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Order::Asc => "ASC",
+            Order::Desc => "DESC",
+        }
+    }
+
+    /// Comparison that continues past the cursor in this order
+    fn after_op(&self) -> &'static str {
+        match self {
+            Order::Asc => ">",
+            Order::Desc => "<",
+        }
+    }
+}
+
+/// Query parameters of time series endpoints
+#[derive(Debug, serde::Deserialize)]
+pub struct PageParams {
+    #[serde(default, deserialize_with = "date_opt_deserialize")]
+    from: Option<time::Date>,
+    #[serde(default, deserialize_with = "date_opt_deserialize")]
+    to: Option<time::Date>,
+    limit: Option<i64>,
+    #[serde(default, deserialize_with = "date_opt_deserialize")]
+    cursor: Option<time::Date>,
+    #[serde(default)]
+    order: Order,
+}
+
+/// Validated window of a time series; `cursor` is the last date of the previous page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub from: Option<time::Date>,
+    pub to: Option<time::Date>,
+    pub cursor: Option<time::Date>,
+    pub limit: i64,
+    pub order: Order,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self {
+            from: None,
+            to: None,
+            cursor: None,
+            limit: DEFAULT_LIMIT,
+            order: Order::Desc,
+        }
+    }
+}
+
+impl TryFrom<PageParams> for Page {
+    type Error = Error;
+
+    fn try_from(params: PageParams) -> Result<Self> {
+        let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(Error::E400BadRequest(format!(
+                "limit must be between 1 and {}",
+                MAX_LIMIT
+            )));
+        }
+        if let (Some(from), Some(to)) = (params.from, params.to)
+            && from > to
+        {
+            return Err(Error::E400BadRequest("from is after to".into()));
+        }
+
+        Ok(Self {
+            from: params.from,
+            to: params.to,
+            cursor: params.cursor,
+            limit,
+            order: params.order,
+        })
+    }
+}
+
+impl Page {
+    /// Select rows of `table` where `key_col=$1` windowed on `date_col`
+    ///
+    /// Binds $2 from, $3 to, $4 cursor and $5 limit; one extra row is fetched to detect the next page
+    pub fn sql(&self, table: &str, key_col: &str, key_type: &str, date_col: &str) -> String {
+        format!(
+            "SELECT * FROM {table} WHERE {key_col}=$1::{key_type}
+                AND ($2::DATE IS NULL OR {date_col} >= $2::DATE)
+                AND ($3::DATE IS NULL OR {date_col} <= $3::DATE)
+                AND ($4::DATE IS NULL OR {date_col} {op} $4::DATE)
+            ORDER BY {date_col} {order} LIMIT $5::BIGINT;",
+            op = self.order.after_op(),
+            order = self.order.as_sql(),
+        )
+    }
+
+    /// Limit bound to the query; see `Page::sql`
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    /// Drop the extra row fetched past the limit and return the cursor of the next page
+    pub fn split<T>(
+        &self,
+        items: &mut Vec<T>,
+        date: impl Fn(&T) -> time::Date,
+    ) -> Option<time::Date> {
+        let limit = self.limit as usize;
+        if items.len() <= limit {
+            return None;
+        }
+        items.truncate(limit);
+        items.last().map(date)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::macros::date;
+
+    fn params(limit: Option<i64>, from: Option<time::Date>, to: Option<time::Date>) -> PageParams {
+        PageParams {
+            from,
+            to,
+            limit,
+            cursor: None,
+            order: Order::Desc,
+        }
+    }
+
+    #[test]
+    fn validates_params() {
+        assert_eq!(
+            Page::try_from(params(None, None, None)).unwrap(),
+            Page::default()
+        );
+        assert!(Page::try_from(params(Some(0), None, None)).is_err());
+        assert!(Page::try_from(params(Some(MAX_LIMIT + 1), None, None)).is_err());
+        assert!(
+            Page::try_from(params(
+                None,
+                Some(date!(2024 - 02 - 01)),
+                Some(date!(2024 - 01 - 01))
+            ))
+            .is_err()
+        );
+        assert!(
+            Page::try_from(params(
+                None,
+                Some(date!(2024 - 01 - 01)),
+                Some(date!(2024 - 01 - 01))
+            ))
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn sql_follows_order() {
+        let mut page = Page::default();
+        let sql = page.sql("price", "srtn_cd", "CHAR(6)", "bas_dt");
+        assert!(sql.contains("bas_dt < $4::DATE"));
+        assert!(sql.contains("ORDER BY bas_dt DESC"));
+
+        page.order = Order::Asc;
+        let sql = page.sql("price", "srtn_cd", "CHAR(6)", "bas_dt");
+        assert!(sql.contains("bas_dt > $4::DATE"));
+        assert!(sql.contains("ORDER BY bas_dt ASC"));
+    }
+
+    #[test]
+    fn split_returns_next_cursor() {
+        let page = Page {
+            limit: 2,
+            ..Page::default()
+        };
+        let days = [
+            date!(2024 - 01 - 04),
+            date!(2024 - 01 - 03),
+            date!(2024 - 01 - 02),
+        ];
+
+        let mut items = days.to_vec();
+        assert_eq!(page.split(&mut items, |d| *d), Some(date!(2024 - 01 - 03)));
+        assert_eq!(items.len(), 2);
+
+        let mut items = days[..2].to_vec();
+        assert_eq!(page.split(&mut items, |d| *d), None);
+        assert_eq!(items.len(), 2);
+    }
+}
@@ -0,0 +1,165 @@
+use super::{Result, auth, cache, error::Error, settings};
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+use deadpool_redis::redis;
+
+// Token bucket refilled continuously; uses Redis time so that every instance shares one clock
+const SCRIPT: &str = r"
+local capacity = tonumber(ARGV[1])
+local rate = tonumber(ARGV[2])
+local t = redis.call('TIME')
+local now = tonumber(t[1]) * 1000 + math.floor(tonumber(t[2]) / 1000)
+local data = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(data[1]) or capacity
+local ts = tonumber(data[2]) or now
+tokens = math.min(capacity, tokens + (now - ts) / 1000 * rate)
+local retry_after = 0
+if tokens >= 1 then
+  tokens = tokens - 1
+else
+  retry_after = math.ceil((1 - tokens) / rate)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / rate * 1000))
+return retry_after
+";
+
+/// Middleware: limit requests per client and route group, e.g. `/v1/dart/...` is group `dart`
+///
+/// Runs before `auth::authorize` so that requests with a wrong key are counted as well
+pub async fn limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> std::result::Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let app_settings = settings::Settings::instance();
+    if !app_settings.rate_limit.enabled {
+        return next.call(req).await;
+    }
+
+    let group = route_group(req.path()).to_string();
+    let client = match auth::client(&req) {
+        Some(c) => format!("key:{}", c.name),
+        None => {
+            let forwarded_for = req
+                .headers()
+                .get("X-Forwarded-For")
+                .and_then(|v| v.to_str().ok());
+            let ip = client_ip(
+                req.peer_addr().map(|a| a.ip()),
+                forwarded_for,
+                &app_settings.rate_limit.trusted_proxies,
+            );
+            format!("ip:{}", ip.map_or("unknown".into(), |ip| ip.to_string()))
+        }
+    };
+
+    // Fail open: an unavailable Redis should not take the API down with it
+    if let Some(retry_after) = take_token(&group, &client).await
+        && retry_after > 0
+    {
+        return Err(Error::E429TooManyRequests(
+            format!("rate limit exceeded for {}", group),
+            retry_after,
+        )
+        .into());
+    }
+
+    next.call(req).await
+}
+
+/// Seconds to wait before the next request is allowed; 0 means the request may go through
+///
+/// `None` when Redis could not be used
+async fn take_token(group: &str, client: &str) -> Option<u64> {
+    let app_settings = settings::Settings::instance();
+    let limit = app_settings
+        .rate_limit
+        .groups
+        .get(group)
+        .unwrap_or(&app_settings.rate_limit.default);
+
+    let key = cache::get_key_prefixed(&format!("rate_limit:{}:{}", group, client));
+    cache::call_redis(async {
+        let mut conn = cache::pool().get().await?;
+        let retry_after = redis::cmd("EVAL")
+            .arg(SCRIPT)
+            .arg(1)
+            .arg(key)
+            .arg(limit.capacity)
+            .arg(limit.refill_per_sec)
+            .query_async::<u64>(&mut conn)
+            .await?;
+        Result::Ok(retry_after)
+    })
+    .await
+}
+
+/// Address of the client; `X-Forwarded-For` is read right to left and only through trusted proxies
+fn client_ip(
+    peer: Option<std::net::IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted: &[std::net::IpAddr],
+) -> Option<std::net::IpAddr> {
+    let mut ip = peer?;
+    let hops = forwarded_for.unwrap_or_default().rsplit(',');
+    for hop in hops {
+        if !trusted.contains(&ip) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(next) => ip = next,
+            Err(_) => break,
+        }
+    }
+    Some(ip)
+}
+
+fn route_group(path: &str) -> &str {
+    path.trim_start_matches('/')
+        .split('/')
+        .nth(1)
+        .filter(|s| !s.is_empty())
+        .unwrap_or("default")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn group_by_path() {
+        assert_eq!(route_group("/v1/prices/005930/daily"), "prices");
+        assert_eq!(route_group("/v1/prices_us/AAPL/latest"), "prices_us");
+        assert_eq!(route_group("/v1/dart/code/005930"), "dart");
+        assert_eq!(route_group("/v1"), "default");
+    }
+
+    #[test]
+    fn ip_through_trusted_proxies() {
+        let ip = |s: &str| s.parse::<std::net::IpAddr>().unwrap();
+        let proxy = [ip("10.0.0.1")];
+
+        // A client cannot pick its own address
+        assert_eq!(
+            client_ip(Some(ip("1.2.3.4")), Some("5.6.7.8"), &proxy),
+            Some(ip("1.2.3.4"))
+        );
+        // The proxy appends the address it saw; anything the client sent before it is ignored
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), Some("5.6.7.8, 1.2.3.4"), &proxy),
+            Some(ip("1.2.3.4"))
+        );
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), None, &proxy),
+            Some(ip("10.0.0.1"))
+        );
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), Some("junk"), &proxy),
+            Some(ip("10.0.0.1"))
+        );
+        assert_eq!(client_ip(None, Some("5.6.7.8"), &proxy), None);
+    }
+}
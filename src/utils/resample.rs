@@ -0,0 +1,340 @@
+use super::{Result, datetime::date_opt_deserialize, error::Error, page::Order};
+
+/// Buckets are aligned to this Sunday unless an anchor is given
+pub const DEFAULT_ANCHOR: time::Date = time::macros::date!(1970 - 01 - 04);
+/// Months start on the first unless an anchor is given
+pub const DEFAULT_MONTH_ANCHOR: time::Date = time::macros::date!(1970 - 01 - 01);
+pub const MAX_COUNT: u32 = 366;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Day, // calendar days, not trading days
+    Week,
+    Month,
+}
+
+/// Bar width such as `3d`, `2w` or `1m`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub count: u32,
+    pub unit: Unit,
+}
+
+impl std::str::FromStr for Interval {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::E400BadRequest(format!("invalid interval: {}", s));
+
+        let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+        let count: u32 = s[..split].parse().map_err(|_| invalid())?;
+        let unit = match &s[split..] {
+            "d" => Unit::Day,
+            "w" => Unit::Week,
+            "m" => Unit::Month,
+            _ => return Err(invalid()),
+        };
+        if !(1..=MAX_COUNT).contains(&count) {
+            return Err(invalid());
+        }
+
+        Ok(Self { count, unit })
+    }
+}
+
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.unit {
+            Unit::Day => "d",
+            Unit::Week => "w",
+            Unit::Month => "m",
+        };
+        write!(f, "{}{}", self.count, unit)
+    }
+}
+
+/// Query parameters of bars endpoints
+#[derive(Debug, serde::Deserialize)]
+pub struct BarsParams {
+    interval: String,
+    anchor: Option<String>,
+    #[serde(default, deserialize_with = "date_opt_deserialize")]
+    from: Option<time::Date>,
+    #[serde(default, deserialize_with = "date_opt_deserialize")]
+    to: Option<time::Date>,
+    #[serde(default)]
+    order: Order,
+}
+
+/// Validated resampling request
+///
+/// One bar starts at `anchor` and the others follow every `interval` before and after it.
+/// A weekday name as the anchor starts weeks on that day. Months start on the day of the month
+/// of the anchor, or on the last day of a shorter month.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bars {
+    pub interval: Interval,
+    pub anchor: time::Date,
+    pub from: Option<time::Date>,
+    pub to: Option<time::Date>,
+    pub order: Order,
+}
+
+/// Items that fall into one bar
+#[derive(Debug)]
+pub struct Bucket<'a, T> {
+    pub start: time::Date,
+    pub items: Vec<&'a T>,
+}
+
+impl TryFrom<BarsParams> for Bars {
+    type Error = Error;
+
+    fn try_from(params: BarsParams) -> Result<Self> {
+        let interval: Interval = params.interval.parse()?;
+        let anchor = match (params.anchor, interval.unit) {
+            (Some(anchor), Unit::Month) => parse_date(&anchor)?,
+            (Some(anchor), _) => parse_anchor(&anchor)?,
+            (None, Unit::Month) => DEFAULT_MONTH_ANCHOR,
+            (None, _) => DEFAULT_ANCHOR,
+        };
+        if let (Some(from), Some(to)) = (params.from, params.to)
+            && from > to
+        {
+            return Err(Error::E400BadRequest("from is after to".into()));
+        }
+
+        Ok(Self {
+            interval,
+            anchor,
+            from: params.from,
+            to: params.to,
+            order: params.order,
+        })
+    }
+}
+
+impl Bars {
+    /// Number of the bar that contains the date; the bar starting at the anchor is 0
+    pub fn index(&self, date: time::Date) -> i64 {
+        let count = i64::from(self.interval.count);
+        match self.interval.unit {
+            Unit::Day => (date - self.anchor).whole_days().div_euclid(count),
+            Unit::Week => (date - self.anchor).whole_days().div_euclid(count * 7),
+            Unit::Month => {
+                // Days before the anchor day belong to the bar of the month before
+                let before = date.day() < self.anchor_day(date.year(), date.month());
+                (months(date) - i64::from(before) - months(self.anchor)).div_euclid(count)
+            }
+        }
+    }
+
+    /// First day of the bar numbered `index`
+    pub fn start(&self, index: i64) -> Result<time::Date> {
+        let count = i64::from(self.interval.count);
+        let date = match self.interval.unit {
+            Unit::Day => self.anchor.checked_add(time::Duration::days(index * count)),
+            Unit::Week => self
+                .anchor
+                .checked_add(time::Duration::weeks(index * count)),
+            Unit::Month => {
+                let months = months(self.anchor) + index * count;
+                let year = i32::try_from(months.div_euclid(12))?;
+                let month = time::Month::try_from(u8::try_from(months.rem_euclid(12) + 1)?)?;
+                let day = self.anchor_day(year, month);
+                Some(time::Date::from_calendar_date(year, month, day)?)
+            }
+        };
+        date.ok_or_else(|| Error::E400BadRequest("date out of range".into()))
+    }
+
+    /// Day a bar starts on in a month, the last one if the month is shorter than the anchor's
+    fn anchor_day(&self, year: i32, month: time::Month) -> u8 {
+        self.anchor.day().min(month.length(year))
+    }
+
+    /// First day of the bar that contains `from`, so that the first bar is complete
+    pub fn query_from(&self) -> Result<Option<time::Date>> {
+        self.from
+            .map(|from| self.start(self.index(from)))
+            .transpose()
+    }
+
+    /// Group items sorted by date in ascending order into consecutive bars
+    pub fn group<'a, T>(
+        &self,
+        items: &'a [T],
+        date: impl Fn(&T) -> time::Date,
+    ) -> Result<Vec<Bucket<'a, T>>> {
+        let mut buckets: Vec<Bucket<'a, T>> = Vec::new();
+        let mut last_index = None;
+
+        for item in items {
+            let index = self.index(date(item));
+            match buckets.last_mut() {
+                Some(bucket) if last_index == Some(index) => bucket.items.push(item),
+                _ => {
+                    buckets.push(Bucket {
+                        start: self.start(index)?,
+                        items: vec![item],
+                    });
+                    last_index = Some(index);
+                }
+            }
+        }
+
+        if self.order == Order::Desc {
+            buckets.reverse();
+        }
+        Ok(buckets)
+    }
+}
+
+fn months(date: time::Date) -> i64 {
+    i64::from(date.year()) * 12 + i64::from(u8::from(date.month())) - 1
+}
+
+/// Anchor as a date or as a weekday name
+fn parse_anchor(s: &str) -> Result<time::Date> {
+    let weekday = match s.to_lowercase().as_str() {
+        "sunday" | "sun" => Some(time::Weekday::Sunday),
+        "monday" | "mon" => Some(time::Weekday::Monday),
+        "tuesday" | "tue" => Some(time::Weekday::Tuesday),
+        "wednesday" | "wed" => Some(time::Weekday::Wednesday),
+        "thursday" | "thu" => Some(time::Weekday::Thursday),
+        "friday" | "fri" => Some(time::Weekday::Friday),
+        "saturday" | "sat" => Some(time::Weekday::Saturday),
+        _ => None,
+    };
+    if let Some(weekday) = weekday {
+        // The default anchor itself when it falls on the weekday
+        let day_before = DEFAULT_ANCHOR.saturating_sub(time::Duration::DAY);
+        return Ok(day_before.next_occurrence(weekday));
+    }
+
+    parse_date(s)
+}
+
+/// Anchor of month bars, which have no weekday
+fn parse_date(s: &str) -> Result<time::Date> {
+    let format = time::macros::format_description!("[year]-[month]-[day]");
+    time::Date::parse(s, &format)
+        .map_err(|_| Error::E400BadRequest(format!("invalid anchor: {}", s)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use time::macros::date;
+
+    fn bars(interval: &str, anchor: Option<&str>) -> Bars {
+        Bars::try_from(BarsParams {
+            interval: interval.into(),
+            anchor: anchor.map(String::from),
+            from: None,
+            to: None,
+            order: Order::Asc,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn parses_interval() {
+        assert_eq!(
+            "3d".parse::<Interval>().unwrap(),
+            Interval {
+                count: 3,
+                unit: Unit::Day
+            }
+        );
+        assert_eq!("2w".parse::<Interval>().unwrap().to_string(), "2w");
+        assert!("0d".parse::<Interval>().is_err());
+        assert!("w".parse::<Interval>().is_err());
+        assert!("3y".parse::<Interval>().is_err());
+        assert!("3".parse::<Interval>().is_err());
+    }
+
+    #[test]
+    fn weeks_start_on_anchor_weekday() {
+        assert_eq!(bars("1w", Some("sunday")).anchor, DEFAULT_ANCHOR);
+
+        let b = bars("1w", Some("monday"));
+        assert_eq!(b.anchor.weekday(), time::Weekday::Monday);
+
+        // Wed 2025-01-01 belongs to the week starting Mon 2024-12-30
+        let index = b.index(date!(2025 - 01 - 01));
+        assert_eq!(b.start(index).unwrap(), date!(2024 - 12 - 30));
+
+        let b = bars("2w", Some("2024-01-01"));
+        let index = b.index(date!(2024 - 01 - 20));
+        assert_eq!(b.start(index).unwrap(), date!(2024 - 01 - 15));
+        let index = b.index(date!(2023 - 12 - 31));
+        assert_eq!(b.start(index).unwrap(), date!(2023 - 12 - 18));
+    }
+
+    #[test]
+    fn months_align_to_anchor() {
+        let b = bars("3m", Some("2024-02-01"));
+        let index = b.index(date!(2024 - 07 - 31));
+        assert_eq!(b.start(index).unwrap(), date!(2024 - 05 - 01));
+        let index = b.index(date!(2024 - 01 - 31));
+        assert_eq!(b.start(index).unwrap(), date!(2023 - 11 - 01));
+
+        let b = bars("1m", None);
+        let index = b.index(date!(2024 - 07 - 31));
+        assert_eq!(b.start(index).unwrap(), date!(2024 - 07 - 01));
+    }
+
+    #[test]
+    fn months_start_on_anchor_day() {
+        let b = bars("3m", Some("2024-02-15"));
+        let index = b.index(date!(2024 - 08 - 14));
+        assert_eq!(b.start(index).unwrap(), date!(2024 - 05 - 15));
+        let index = b.index(date!(2024 - 08 - 15));
+        assert_eq!(b.start(index).unwrap(), date!(2024 - 08 - 15));
+
+        // The 31st falls back to the end of shorter months
+        let b = bars("1m", Some("2024-01-31"));
+        let index = b.index(date!(2024 - 03 - 30));
+        assert_eq!(b.start(index).unwrap(), date!(2024 - 02 - 29));
+        let index = b.index(date!(2024 - 03 - 31));
+        assert_eq!(b.start(index).unwrap(), date!(2024 - 03 - 31));
+
+        let params = BarsParams {
+            interval: "1m".into(),
+            anchor: Some("monday".into()),
+            from: None,
+            to: None,
+            order: Order::Asc,
+        };
+        assert!(matches!(
+            Bars::try_from(params),
+            Err(Error::E400BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn groups_consecutive_items() {
+        let b = bars("3d", Some("2024-01-01"));
+        let days = [
+            date!(2024 - 01 - 02),
+            date!(2024 - 01 - 03),
+            date!(2024 - 01 - 04),
+            date!(2024 - 01 - 05),
+            date!(2024 - 01 - 08),
+        ];
+
+        let buckets = b.group(&days, |d| *d).unwrap();
+        let starts: Vec<_> = buckets.iter().map(|v| v.start).collect();
+        let sizes: Vec<_> = buckets.iter().map(|v| v.items.len()).collect();
+        assert_eq!(
+            starts,
+            [
+                date!(2024 - 01 - 01),
+                date!(2024 - 01 - 04),
+                date!(2024 - 01 - 07)
+            ]
+        );
+        assert_eq!(sizes, [2, 2, 1]);
+    }
+}
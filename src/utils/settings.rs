@@ -1,4 +1,9 @@
-use super::Result;
+use super::{
+    Result,
+    auth::Role,
+    datetime::{date_deserialize, date_serialize},
+    error::Error,
+};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Server {
@@ -49,6 +54,314 @@ pub struct Urls {
     pub edgar: String,
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ScheduledJob {
+    pub enabled: bool,
+    pub cron: String, // sec min hour day_of_month month day_of_week [year]
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Scheduler {
+    pub enabled: bool,
+    pub utc_offset: Option<String>, // e.g. "+09:00"; cron expressions are in UTC when omitted
+    pub jobs: std::collections::BTreeMap<String, ScheduledJob>,
+}
+
+impl Scheduler {
+    /// Offset the cron expressions are evaluated in
+    pub fn offset(&self) -> Result<chrono::FixedOffset> {
+        let Some(s) = &self.utc_offset else {
+            return Ok(chrono::FixedOffset::east_opt(0).unwrap());
+        };
+        s.parse().map_err(|e| {
+            Error::General(format!(
+                "scheduler.utc_offset {} is not like \"+09:00\": {}",
+                s, e
+            ))
+        })
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Jobs {
+    pub workers: usize,
+    pub poll_interval_secs: u64,
+    pub heartbeat_secs: u64,
+    pub stale_secs: u64, // a running job without a heartbeat for this long is queued again
+    pub retention_days: u32, // finished jobs are removed afterwards
+}
+
+impl Jobs {
+    fn validate(&self) -> Result<()> {
+        // A running job would otherwise be taken for abandoned between two heartbeats
+        if self.stale_secs <= self.heartbeat_secs.max(1) {
+            return Err(Error::General(format!(
+                "jobs.stale_secs ({}) must be greater than jobs.heartbeat_secs ({})",
+                self.stale_secs, self.heartbeat_secs
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Jobs {
+    fn default() -> Self {
+        Self {
+            workers: 2,
+            poll_interval_secs: 5,
+            heartbeat_secs: 15,
+            stale_secs: 120,
+            retention_days: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Migrations {
+    pub on_startup: bool, // otherwise run the binary with `--migrate` before starting it
+}
+
+impl Default for Migrations {
+    fn default() -> Self {
+        Self { on_startup: true }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Auth {
+    pub enabled: bool,
+    pub keys: Vec<ApiKey>,
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            keys: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Bucket {
+    pub capacity: u32,
+    pub refill_per_sec: f64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RateLimit {
+    pub enabled: bool,
+    pub default: Bucket,
+    pub groups: std::collections::BTreeMap<String, Bucket>, // keyed by the path segment after `/v1`
+    pub trusted_proxies: Vec<std::net::IpAddr>,             // only these may set `X-Forwarded-For`
+}
+
+impl RateLimit {
+    fn validate(&self) -> Result<()> {
+        let buckets = std::iter::once(("default", &self.default))
+            .chain(self.groups.iter().map(|(k, v)| (k.as_str(), v)));
+        for (name, bucket) in buckets {
+            if !(bucket.refill_per_sec.is_finite() && bucket.refill_per_sec > 0.0) {
+                return Err(Error::General(format!(
+                    "rate_limit.{}.refill_per_sec must be greater than 0",
+                    name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            default: Bucket {
+                capacity: 120,
+                refill_per_sec: 2.0,
+            },
+            groups: std::collections::BTreeMap::new(),
+            trusted_proxies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CachePolicy {
+    pub fresh_secs: u64,
+    pub stale_secs: u64, // served while refreshing in the background; removed afterwards
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            fresh_secs: 86400,
+            stale_secs: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Cache {
+    pub timeout_ms: u64,
+    pub backoff_min_secs: u64,
+    pub backoff_max_secs: u64,
+    pub fallback_max_entries: usize,
+    pub compress: bool,
+    pub compress_min_bytes: usize,
+    pub schema_version: u32,
+    pub lock_ttl_ms: u64,
+    pub lock_wait_ms: u64,
+    pub policies: std::collections::BTreeMap<String, CachePolicy>,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 500,
+            backoff_min_secs: 1,
+            backoff_max_secs: 60,
+            fallback_max_entries: 1000,
+            compress: true,
+            compress_min_bytes: 1024,
+            schema_version: 1,
+            lock_ttl_ms: 30000,
+            lock_wait_ms: 15000,
+            policies: std::collections::BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Calendar {
+    pub quote_ttl_open_secs: u64,
+    pub krx_holidays: Vec<String>, // "YYYY-MM-DD", in addition to the built-in ones
+    pub us_holidays: Vec<String>,
+}
+
+impl Default for Calendar {
+    fn default() -> Self {
+        Self {
+            quote_ttl_open_secs: 60,
+            krx_holidays: Vec::new(),
+            us_holidays: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefreshMode {
+    Inline,     // refresh before responding
+    Background, // respond with what is stored, flagged as stale, and refresh through the job queue
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Freshness {
+    pub enabled: bool,
+    pub mode: RefreshMode,
+    pub throttle_secs: u64, // per symbol
+    pub krx_lag_days: u32,  // trading days the upstream takes to publish a closed session
+    pub us_lag_days: u32,
+}
+
+impl Default for Freshness {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            mode: RefreshMode::Background,
+            throttle_secs: 600,
+            krx_lag_days: 1,
+            us_lag_days: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WeekStart {
+    Iso,    // ISO 8601 weeks starting on Monday
+    Sunday, // weeks starting on Sunday; week 1 contains January 1
+    Monday, // weeks starting on Monday; week 1 contains January 1
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Prices {
+    pub week_start: WeekStart, // weekly rows must be rebuilt after changing it
+    pub market_lookback_days: u32, // trading days fetched again by market-wide updates
+}
+
+impl Default for Prices {
+    fn default() -> Self {
+        Self {
+            week_start: WeekStart::Sunday,
+            market_lookback_days: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Backfill {
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub krx_start: time::Date,
+    #[serde(
+        serialize_with = "date_serialize",
+        deserialize_with = "date_deserialize"
+    )]
+    pub us_start: time::Date,
+    pub chunk_days: u32, // days of history requested at once
+}
+
+impl Default for Backfill {
+    fn default() -> Self {
+        Self {
+            krx_start: time::macros::date!(2020 - 01 - 01),
+            us_start: time::macros::date!(2020 - 01 - 01),
+            chunk_days: 365,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Reload {
+    // A rebuild with fewer rows is rejected and the current table is kept
+    pub ticker_min_rows: u64,
+    pub company_min_rows: u64,
+    pub dart_code_min_rows: u64,
+}
+
+impl Default for Reload {
+    fn default() -> Self {
+        Self {
+            ticker_min_rows: 5000,
+            company_min_rows: 2000,
+            dart_code_min_rows: 2000,
+        }
+    }
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Settings {
     pub server: Server,
@@ -57,8 +370,30 @@ pub struct Settings {
     pub cors: Cors,
     pub pg: deadpool_postgres::Config,
     pub redis: deadpool_redis::Config,
+    #[serde(default)]
+    pub cache: Cache,
     pub keys: Keys,
     pub urls: Urls,
+    #[serde(default)]
+    pub calendar: Calendar,
+    #[serde(default)]
+    pub freshness: Freshness,
+    #[serde(default)]
+    pub prices: Prices,
+    #[serde(default)]
+    pub backfill: Backfill,
+    #[serde(default)]
+    pub reload: Reload,
+    #[serde(default)]
+    pub scheduler: Scheduler,
+    #[serde(default)]
+    pub jobs: Jobs,
+    #[serde(default)]
+    pub migrations: Migrations,
+    #[serde(default)]
+    pub auth: Auth,
+    #[serde(default)]
+    pub rate_limit: RateLimit,
 }
 
 static SETTINGS: std::sync::OnceLock<Settings> = std::sync::OnceLock::new();
@@ -76,7 +411,11 @@ impl Settings {
             .add_source(config::Environment::with_prefix("APP"))
             .build()?;
         println!("{:?}", s);
-        Ok(s.try_deserialize()?)
+        let settings: Self = s.try_deserialize()?;
+        settings.rate_limit.validate()?;
+        settings.scheduler.offset()?;
+        settings.jobs.validate()?;
+        Ok(settings)
     }
 
     #[tracing::instrument]
@@ -40,7 +40,7 @@ impl OpenTelemetry {
 
         let jaeger_layer = tracing_opentelemetry::layer().with_tracer(tracer);
 
-        // metrics with opentelemetry + prometheus
+        // metrics are exported separately by utils::metrics for prometheus to scrape
         Self { jaeger_layer }
     }
 }